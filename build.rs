@@ -1,36 +1,67 @@
-use reqwest::blocking::get;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Write;
-use std::path::Path;
+use std::path::PathBuf;
+
+#[allow(dead_code)]
+#[path = "src/suffix_set.rs"]
+mod suffix_set;
+
+const TLDS_PATH: &str = "./data/tlds-alpha-by-domain.txt";
+const PSL_PATH: &str = "./data/public_suffix_list.dat";
 
 fn main() {
-    let tlds_path = Path::new("./src/tlds.rs");
-    if !tlds_path.exists() {
-        // Download TLDs file
-        let tlds_url = "https://data.iana.org/TLD/tlds-alpha-by-domain.txt";
-        let response = get(tlds_url).expect("Failed to download TLDs file");
-        let content = response.text().expect("Failed to read response");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+
+    #[cfg(feature = "refresh-data")]
+    let (tlds_path, psl_path) = refresh_data(&out_dir);
+    #[cfg(not(feature = "refresh-data"))]
+    let (tlds_path, psl_path) = (PathBuf::from(TLDS_PATH), PathBuf::from(PSL_PATH));
+
+    let tlds = suffix_set::parse_tlds(&fs::read_to_string(tlds_path).unwrap());
+    let psl = suffix_set::parse_psl(&fs::read_to_string(psl_path).unwrap());
 
-        // Parse and process TLDs
-        let tlds = content
-            .lines()
-            .skip(1)
-            .map(|s| s.to_lowercase())
-            .collect::<Vec<String>>();
-
-        // Write to output file
-        let out_dir = "./src/".to_owned();
-        let dest_path = Path::new(&out_dir).join("tlds.rs");
-        let mut f = File::create(dest_path).unwrap();
-
-        writeln!(f, "pub const TLDS: &[&str] = &[").unwrap();
-        for tld in tlds {
-            writeln!(f, "    \"{}\",", tld).unwrap();
+    // Lay the lists out as `SuffixSet` tables
+    let mut f = File::create(out_dir.join("suffix_sets.rs")).unwrap();
+    for (name, names) in [
+        ("TLDS", tlds),
+        ("PSL_RULES", psl.rules),
+        ("PSL_WILDCARDS", psl.wildcards),
+        ("PSL_EXCEPTIONS", psl.exceptions),
+    ] {
+        writeln!(f, "pub static {}: SuffixSet = SuffixSet::new(&[", name).unwrap();
+        for slot in suffix_set::layout(&names) {
+            writeln!(f, "    \"{}\",", slot).unwrap();
         }
-        writeln!(f, "];").unwrap();
-    } else {
-        println!("tlds.rs already exists, skipping download");
+        writeln!(f, "]);").unwrap();
     }
 
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/suffix_set.rs");
+    println!("cargo:rerun-if-changed={}", TLDS_PATH);
+    println!("cargo:rerun-if-changed={}", PSL_PATH);
+}
+
+/// Downloads the latest published lists into `out_dir`, in place of the
+/// vendored ones. The sources are left alone.
+#[cfg(feature = "refresh-data")]
+fn refresh_data(out_dir: &std::path::Path) -> (PathBuf, PathBuf) {
+    use reqwest::blocking::get;
+
+    let download = |url: &str, file: &str| {
+        let response = get(url).expect("Failed to download suffix list");
+        let content = response.text().expect("Failed to read response");
+        let path = out_dir.join(file);
+        fs::write(&path, content).unwrap();
+        path
+    };
+    (
+        download(
+            "https://data.iana.org/TLD/tlds-alpha-by-domain.txt",
+            "tlds-alpha-by-domain.txt",
+        ),
+        download(
+            "https://publicsuffix.org/list/public_suffix_list.dat",
+            "public_suffix_list.dat",
+        ),
+    )
 }
@@ -0,0 +1,362 @@
+use futures::{FutureExt, Stream, StreamExt};
+use k8s_openapi::api::core::v1::{Namespace, Service};
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::{
+    Api, Client, Resource,
+    core::PartialObjectMeta,
+    runtime::{
+        WatchStreamExt, metadata_watcher,
+        reflector::{self, ObjectRef, Store},
+        watcher::{self, Event, watcher},
+    },
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+use crate::domain::{InternalName, IpOwner, ServiceId};
+
+/// Services and namespaces of the cluster, kept up to date by watches so
+/// internal names can be checked against what actually exists.
+#[derive(Clone)]
+pub struct ServiceCatalog {
+    services: Store<Service>,
+    namespaces: Store<Namespace>,
+    /// ClusterIP -> Service, for reverse lookups.
+    cluster_ips: Arc<RwLock<HashMap<IpAddr, ServiceId>>>,
+}
+
+impl ServiceCatalog {
+    pub fn start(client: &Client) -> Self {
+        let (services, writer) = reflector::store();
+        let cluster_ips = Arc::new(RwLock::new(HashMap::new()));
+        let stream = watcher(
+            Api::<Service>::all(client.clone()),
+            watcher::Config::default(),
+        )
+        .default_backoff()
+        .reflect(writer);
+
+        let index = cluster_ips.clone();
+        tokio::spawn(async move {
+            let mut stream = std::pin::pin!(stream);
+            let mut relisted = HashMap::new();
+            while let Some(event) = stream.next().await {
+                match event {
+                    Ok(Event::Apply(service)) => {
+                        let mut index = index.write().unwrap();
+                        if let Some(id) = service_id(&service) {
+                            index.retain(|_, owner| *owner != id);
+                        }
+                        index_service(&mut index, &service);
+                    }
+                    Ok(Event::Delete(service)) => {
+                        if let Some(id) = service_id(&service) {
+                            index.write().unwrap().retain(|_, owner| *owner != id);
+                        }
+                    }
+                    Ok(Event::Init) => relisted.clear(),
+                    Ok(Event::InitApply(service)) => index_service(&mut relisted, &service),
+                    Ok(Event::InitDone) => {
+                        *index.write().unwrap() = std::mem::take(&mut relisted);
+                    }
+                    Err(err) => log::error!("Service watch failed: {}", err),
+                }
+            }
+        });
+
+        Self {
+            services,
+            namespaces: reflect(Api::all(client.clone())),
+            cluster_ips,
+        }
+    }
+
+    /// Why the name cannot resolve, `None` if it can or the watches have not
+    /// listed everything yet.
+    pub fn check(&self, name: &InternalName) -> Option<DanglingReason> {
+        if !is_ready(&self.services) || !is_ready(&self.namespaces) {
+            return None;
+        }
+        let (namespace, service) = match name {
+            InternalName::Service(service) | InternalName::Srv(service) => {
+                (&service.namespace, Some(service))
+            }
+            InternalName::Endpoint { service, .. } => (&service.namespace, Some(service)),
+            InternalName::Pod { namespace, .. } => (namespace, None),
+            // Not a name the `kubernetes` plugin could ever answer.
+            InternalName::Other(_) => return None,
+        };
+
+        if self.namespaces.get(&ObjectRef::new(namespace)).is_none() {
+            return Some(DanglingReason::MissingNamespace);
+        }
+        match service {
+            Some(service) if self.service(service).is_none() => {
+                Some(DanglingReason::MissingService)
+            }
+            _ => None,
+        }
+    }
+
+    /// The Service with this ClusterIP.
+    pub fn ip_owner(&self, ip: IpAddr) -> Option<IpOwner> {
+        let cluster_ips = self.cluster_ips.read().unwrap();
+        cluster_ips.get(&ip).cloned().map(IpOwner::Service)
+    }
+
+    fn service(&self, id: &ServiceId) -> Option<Arc<Service>> {
+        self.services
+            .get(&ObjectRef::new(&id.service).within(&id.namespace))
+    }
+}
+
+fn service_id(service: &Service) -> Option<ServiceId> {
+    Some(ServiceId::new(
+        service.metadata.name.as_deref()?,
+        service.metadata.namespace.as_deref()?,
+    ))
+}
+
+/// Adds the ClusterIPs of the service, headless services have none.
+fn index_service(index: &mut HashMap<IpAddr, ServiceId>, service: &Service) {
+    let Some(id) = service_id(service) else {
+        return;
+    };
+    let cluster_ips = service
+        .spec
+        .iter()
+        .flat_map(|spec| spec.cluster_ips.iter().flatten())
+        .filter_map(|addr| addr.parse::<IpAddr>().ok());
+    for ip in cluster_ips {
+        index.insert(ip.to_canonical(), id.clone());
+    }
+}
+
+/// Keeps a store of every object of the kind, re-listing on errors.
+pub fn reflect<K>(api: Api<K>) -> Store<K>
+where
+    K: Resource<DynamicType = ()> + Clone + std::fmt::Debug + Send + Sync + 'static,
+    K: serde::de::DeserializeOwned,
+{
+    let (store, writer) = reflector::store();
+    let stream = watcher(api, watcher::Config::default())
+        .default_backoff()
+        .reflect(writer);
+    drive::<K, _>(stream);
+    store
+}
+
+/// Keeps a store of the metadata of every object of the kind, for kinds
+/// only looked at for their names, labels or ownerReferences.
+pub fn reflect_metadata<K>(api: Api<K>) -> Store<PartialObjectMeta<K>>
+where
+    K: Resource<DynamicType = ()> + Clone + std::fmt::Debug + Send + Sync + 'static,
+    K: serde::de::DeserializeOwned,
+{
+    let (store, writer) = reflector::store();
+    let stream = metadata_watcher(api, watcher::Config::default())
+        .default_backoff()
+        .reflect(writer);
+    drive::<K, _>(stream);
+    store
+}
+
+/// Polls a reflecting watch in the background, logging its errors.
+fn drive<K, E>(stream: impl Stream<Item = watcher::Result<E>> + Send + 'static)
+where
+    K: Resource<DynamicType = ()>,
+    E: Send + 'static,
+{
+    let kind = K::kind(&()).to_string();
+    tokio::spawn(async move {
+        let mut stream = std::pin::pin!(stream);
+        while let Some(event) = stream.next().await {
+            if let Err(err) = event {
+                log::error!("{} watch failed: {}", kind, err);
+            }
+        }
+    });
+}
+
+fn is_ready<K: Resource<DynamicType = ()> + Clone + 'static>(store: &Store<K>) -> bool {
+    store
+        .wait_until_ready()
+        .now_or_never()
+        .is_some_and(|ready| ready.is_ok())
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DanglingReason {
+    MissingNamespace,
+    MissingService,
+}
+
+/// Queries for an internal name that has no Service or namespace behind it.
+#[derive(Serialize, Debug, Clone)]
+pub struct DanglingDependency {
+    pub(crate) reason: DanglingReason,
+    /// Querying pod -> when it was seen asking.
+    pub(crate) pods: HashMap<String, Sighting>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Sighting {
+    pub(crate) queries: u64,
+    pub(crate) first_seen: DateTime<Utc>,
+    pub(crate) last_seen: DateTime<Utc>,
+}
+
+impl Sighting {
+    pub fn merge(&mut self, other: &Sighting) {
+        self.queries += other.queries;
+        self.first_seen = self.first_seen.min(other.first_seen);
+        self.last_seen = self.last_seen.max(other.last_seen);
+    }
+}
+
+impl DanglingDependency {
+    pub fn new(reason: DanglingReason) -> Self {
+        Self {
+            reason,
+            pods: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, pod: &str, at: DateTime<Utc>) {
+        let sighting = self.pods.entry(pod.to_string()).or_insert(Sighting {
+            queries: 0,
+            first_seen: at,
+            last_seen: at,
+        });
+        sighting.queries += 1;
+        sighting.first_seen = sighting.first_seen.min(at);
+        sighting.last_seen = sighting.last_seen.max(at);
+    }
+}
+
+#[cfg(test)]
+impl ServiceCatalog {
+    /// A catalog of the Services and namespaces, as if fully listed.
+    pub(crate) fn listed(services: Vec<Service>, namespaces: Vec<Namespace>) -> Self {
+        Self {
+            services: tests::store(services, true),
+            namespaces: tests::store(namespaces, true),
+            cluster_ips: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn meta(name: &str, namespace: Option<&str>) -> ObjectMeta {
+        ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: namespace.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    /// A store filled by a complete listing, unless `listed` is false.
+    pub(super) fn store<K>(objects: Vec<K>, listed: bool) -> Store<K>
+    where
+        K: Resource<DynamicType = ()> + Clone + std::fmt::Debug + Send + Sync + 'static,
+    {
+        let (store, mut writer) = reflector::store();
+        writer.apply_watcher_event(&Event::Init);
+        for object in objects {
+            writer.apply_watcher_event(&Event::InitApply(object));
+        }
+        if listed {
+            writer.apply_watcher_event(&Event::InitDone);
+        }
+        store
+    }
+
+    fn catalog(listed: bool) -> ServiceCatalog {
+        let service = Service {
+            metadata: meta("web", Some("shop")),
+            ..Default::default()
+        };
+        let namespaces = ["shop", "empty"].map(|name| Namespace {
+            metadata: meta(name, None),
+            ..Default::default()
+        });
+        ServiceCatalog {
+            services: store(vec![service], listed),
+            namespaces: store(namespaces.to_vec(), listed),
+            cluster_ips: Default::default(),
+        }
+    }
+
+    fn service(service: &str, namespace: &str) -> InternalName {
+        InternalName::Service(ServiceId::new(service, namespace))
+    }
+
+    #[test]
+    fn finds_existing_services() {
+        let catalog = catalog(true);
+        assert_eq!(catalog.check(&service("web", "shop")), None);
+        assert_eq!(
+            catalog.check(&InternalName::Srv(ServiceId {
+                port: Some("http".to_string()),
+                proto: Some("tcp".to_string()),
+                ..ServiceId::new("web", "shop")
+            })),
+            None
+        );
+    }
+
+    #[test]
+    fn reports_missing_services_and_namespaces() {
+        let catalog = catalog(true);
+        assert_eq!(
+            catalog.check(&service("api", "shop")),
+            Some(DanglingReason::MissingService)
+        );
+        assert_eq!(
+            catalog.check(&service("web", "empty")),
+            Some(DanglingReason::MissingService)
+        );
+        assert_eq!(
+            catalog.check(&service("web", "gone")),
+            Some(DanglingReason::MissingNamespace)
+        );
+        let member = InternalName::Endpoint {
+            hostname: "web-0".to_string(),
+            service: ServiceId::new("db", "shop"),
+            pod: None,
+        };
+        assert_eq!(catalog.check(&member), Some(DanglingReason::MissingService));
+    }
+
+    #[test]
+    fn checks_only_the_namespace_of_pod_records() {
+        let catalog = catalog(true);
+        let pod = |namespace: &str| InternalName::Pod {
+            ip: "10.244.0.5".parse().unwrap(),
+            namespace: namespace.to_string(),
+            pod: None,
+        };
+        assert_eq!(catalog.check(&pod("shop")), None);
+        assert_eq!(
+            catalog.check(&pod("gone")),
+            Some(DanglingReason::MissingNamespace)
+        );
+        assert_eq!(
+            catalog.check(&InternalName::Other("redis.shop.cluster.local".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn reports_nothing_before_the_first_listing() {
+        let catalog = catalog(false);
+        assert_eq!(catalog.check(&service("api", "gone")), None);
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result, bail};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// A named address range, `vpn=10.8.0.0/16`, for clients that are not pods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CidrLabel {
+    pub(crate) name: String,
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl CidrLabel {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                masked(u32::from(network).into(), 32, self.prefix)
+                    == masked(u32::from(ip).into(), 32, self.prefix)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                masked(network.into(), 128, self.prefix) == masked(ip.into(), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The first `prefix` bits of a `bits` wide address.
+fn masked(address: u128, bits: u8, prefix: u8) -> u128 {
+    match bits - prefix {
+        128 => 0,
+        host => address >> host,
+    }
+}
+
+impl FromStr for CidrLabel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, cidr) = s
+            .split_once('=')
+            .context("Expected <name>=<cidr>, for example vpn=10.8.0.0/16")?;
+        let (network, prefix) = cidr.split_once('/').unwrap_or((cidr, ""));
+        let network = network
+            .parse::<IpAddr>()
+            .with_context(|| format!("Invalid network address in {}", cidr))?
+            .to_canonical();
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            "" => bits,
+            prefix => prefix
+                .parse()
+                .with_context(|| format!("Invalid prefix length in {}", cidr))?,
+        };
+        if name.is_empty() {
+            bail!("Missing label name in {}", s);
+        }
+        if prefix > bits {
+            bail!("Prefix length of {} is longer than the address", cidr);
+        }
+        Ok(Self {
+            name: name.to_string(),
+            network,
+            prefix,
+        })
+    }
+}
+
+/// The most specific label covering the address.
+pub fn label(labels: &[CidrLabel], ip: IpAddr) -> Option<&CidrLabel> {
+    labels
+        .iter()
+        .filter(|label| label.contains(ip))
+        .max_by_key(|label| label.prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_labels() {
+        let label: CidrLabel = "vpn=10.8.0.0/16".parse().unwrap();
+        assert_eq!(label.name, "vpn");
+        assert_eq!(label.network, ip("10.8.0.0"));
+        assert_eq!(label.prefix, 16);
+
+        let host: CidrLabel = "gateway=fd00::1".parse().unwrap();
+        assert_eq!(host.prefix, 128);
+
+        let mapped: CidrLabel = "mapped=::ffff:10.0.0.0/8".parse().unwrap();
+        assert_eq!(mapped.network, ip("10.0.0.0"));
+    }
+
+    #[test]
+    fn rejects_invalid_labels() {
+        assert!("10.8.0.0/16".parse::<CidrLabel>().is_err());
+        assert!("=10.8.0.0/16".parse::<CidrLabel>().is_err());
+        assert!("vpn=10.8.0/16".parse::<CidrLabel>().is_err());
+        assert!("vpn=10.8.0.0/x".parse::<CidrLabel>().is_err());
+        assert!("vpn=10.8.0.0/33".parse::<CidrLabel>().is_err());
+        assert!("vpn=fd00::/129".parse::<CidrLabel>().is_err());
+    }
+
+    #[test]
+    fn matches_addresses_in_range() {
+        let vpn: CidrLabel = "vpn=10.8.0.0/16".parse().unwrap();
+        assert!(vpn.contains(ip("10.8.0.1")));
+        assert!(vpn.contains(ip("10.8.255.255")));
+        assert!(vpn.contains(ip("::ffff:10.8.3.4")));
+        assert!(!vpn.contains(ip("10.9.0.1")));
+        assert!(!vpn.contains(ip("fd00::1")));
+
+        let v6: CidrLabel = "lab=fd00:10::/32".parse().unwrap();
+        assert!(v6.contains(ip("fd00:10::5")));
+        assert!(!v6.contains(ip("fd00:11::5")));
+    }
+
+    #[test]
+    fn matches_everything_with_a_zero_prefix() {
+        let any: CidrLabel = "any=0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("192.168.1.1")));
+        let any: CidrLabel = "any=::/0".parse().unwrap();
+        assert!(any.contains(ip("fd00::1")));
+    }
+
+    #[test]
+    fn picks_the_most_specific_label() {
+        let labels: Vec<CidrLabel> = ["on-prem=10.0.0.0/8", "vpn=10.8.0.0/16"]
+            .iter()
+            .map(|label| label.parse().unwrap())
+            .collect();
+        assert_eq!(label(&labels, ip("10.8.1.1")).unwrap().name, "vpn");
+        assert_eq!(label(&labels, ip("10.1.1.1")).unwrap().name, "on-prem");
+        assert!(label(&labels, ip("192.168.1.1")).is_none());
+    }
+}
@@ -1,8 +1,13 @@
-use clap::Parser;
+use clap::{ArgGroup, Parser, ValueEnum};
 use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::cidr::CidrLabel;
 
 #[derive(Parser)]
 #[clap(version="1.0")]
+// Queries come from a single source, the cluster's logs when none is given.
+#[clap(group(ArgGroup::new("source").args(["input", "dnstap_listen", "dnstap_file"])))]
 #[derive(Debug, Default)]
 pub struct Config {
     #[clap(long, env="SERVER_PORT", default_value = "8080")]
@@ -16,8 +21,165 @@ pub struct Config {
 
     #[clap(long, env="COREDNS_LABEL_SELECTOR", default_value = "k8s-app=kube-dns")]
     pub coredns_label_selector: String,
+
+    /// Read exported CoreDNS logs from a file, or `-` for stdin, instead of
+    /// following the cluster. Gzip compressed files are supported.
+    #[clap(long, env = "INPUT")]
+    pub input: Option<String>,
+
+    /// Replay `--input` or `--dnstap-file` at its original pace sped up by this
+    /// factor (1 is real time). Log lines need timestamps, see
+    /// `kubectl logs --timestamps`.
+    #[clap(long, env = "REPLAY_SPEED", value_parser = parse_replay_speed)]
+    pub replay_speed: Option<f64>,
+
+    /// Receive dnstap from CoreDNS on `unix:///path` or `tcp://host:port`
+    /// instead of following its logs.
+    #[clap(long, env = "DNSTAP_LISTEN")]
+    pub dnstap_listen: Option<String>,
+
+    /// Read a recorded dnstap file instead of following CoreDNS logs.
+    #[clap(long, env = "DNSTAP_FILE")]
+    pub dnstap_file: Option<String>,
+
+    /// Look up pods, Services and nodes in the current kubeconfig cluster
+    /// for `--input`, `--dnstap-listen` and `--dnstap-file` too.
+    #[clap(long, env = "CLUSTER_LOOKUPS")]
+    pub cluster_lookups: bool,
+
+    /// Format of the CoreDNS `log` plugin, using the same placeholders as
+    /// the Corefile (`{remote}`, `{type}`, `{name}`, `{rcode}`, ...).
+    #[clap(long, env = "LOG_FORMAT", default_value = "{common}")]
+    pub log_format: String,
+
+    /// Zones served by the CoreDNS `kubernetes` plugin, names under them
+    /// are classified as internal.
+    #[clap(
+        long,
+        env = "INTERNAL_ZONES",
+        value_delimiter = ',',
+        default_value = "cluster.local"
+    )]
+    pub internal_zones: Vec<String>,
+
+    /// Add the zones of the `kubernetes` plugin found in the CoreDNS Corefile.
+    #[clap(long, env = "DISCOVER_ZONES")]
+    pub discover_zones: bool,
+
+    #[clap(long, env = "COREDNS_CONFIGMAP", default_value = "coredns")]
+    pub coredns_configmap: String,
+
+    /// Level external names are grouped at: the full name, the registrable
+    /// domain (eTLD+1) or the public suffix.
+    #[clap(long, env = "EXTERNAL_AGGREGATION", value_enum, default_value_t)]
+    pub external_aggregation: ExternalAggregation,
+
+    /// Load TLDs from this file (IANA `tlds-alpha-by-domain.txt` format)
+    /// instead of the list compiled in.
+    #[clap(long, env = "TLD_FILE")]
+    pub tld_file: Option<String>,
+
+    /// Load the Public Suffix List from this file instead of the list
+    /// compiled in.
+    #[clap(long, env = "PSL_FILE")]
+    pub psl_file: Option<String>,
+
+    /// Level querying clients are recorded at: the pod, the workload at the
+    /// top of its ownerReferences (Deployment, StatefulSet, CronJob, ...) or
+    /// its namespace. The UIs can group them further, but not split them.
+    #[clap(long, env = "GRANULARITY", value_enum, default_value_t)]
+    pub granularity: Granularity,
+
+    /// Name an address range, `--cidr-label vpn=10.8.0.0/16`. Clients that
+    /// are not pods are kept by address, and grouped by the label covering
+    /// it at workload or namespace granularity.
+    #[clap(long = "cidr-label", env = "CIDR_LABELS", value_delimiter = ',')]
+    pub cidr_labels: Vec<CidrLabel>,
+
+    /// Show the terminal UI instead of serving the web interface.
+    #[clap(long, env = "TUI")]
+    pub tui: bool,
+}
+
+impl Config {
+    /// Whether queries come from the CoreDNS pod logs rather than another source.
+    pub fn follows_cluster(&self) -> bool {
+        self.input.is_none() && self.dnstap_listen.is_none() && self.dnstap_file.is_none()
+    }
+
+    /// Whether clients and targets are resolved against the cluster.
+    pub fn looks_up_cluster(&self) -> bool {
+        self.follows_cluster() || self.cluster_lookups
+    }
+}
+
+fn parse_replay_speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalAggregation {
+    /// `foo.s3.amazonaws.com`
+    #[default]
+    Fqdn,
+    /// `foo.s3.amazonaws.com`, `www.example.co.uk` -> `example.co.uk`
+    Registrable,
+    /// `s3.amazonaws.com`, `co.uk`
+    Suffix,
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    /// `shop/api-7d9f8b6c4-x2kq9`
+    #[default]
+    Pod,
+    /// `shop/deployment/api`
+    Workload,
+    /// `shop`
+    Namespace,
+}
+
+impl Granularity {
+    /// The next coarser granularity, wrapping around to pods.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Pod => Self::Workload,
+            Self::Workload => Self::Namespace,
+            Self::Namespace => Self::Pod,
+        }
+    }
 }
 
 lazy_static! {
-    pub static ref CONFIG: Config = Config::parse();
-}
\ No newline at end of file
+    // The arguments of the test harness are not ours, tests get the defaults.
+    pub static ref CONFIG: Config = if cfg!(test) {
+        Config::parse_from(["scope-rs"])
+    } else {
+        Config::parse()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_a_single_input_source() {
+        let config = Config::try_parse_from(["scope-rs", "--input", "coredns.log"]).unwrap();
+        assert!(!config.follows_cluster());
+        let err = Config::try_parse_from([
+            "scope-rs",
+            "--input",
+            "coredns.log",
+            "--dnstap-file",
+            "capture.dnstap",
+        ])
+        .unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+}
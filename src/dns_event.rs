@@ -0,0 +1,166 @@
+use k8s_openapi::chrono::{DateTime, Utc};
+use regex::Captures;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A single query as logged by CoreDNS. Fields are kept even when no
+/// consumer needs them yet so that the event mirrors the log line, the
+/// ones nothing reads yet allow `dead_code`.
+#[derive(Debug, Clone)]
+pub struct DnsQueryEvent {
+    pub(crate) client_ip: IpAddr,
+    #[allow(dead_code)]
+    pub(crate) client_port: u16,
+    #[allow(dead_code)]
+    pub(crate) id: u16,
+    pub(crate) qtype: String,
+    #[allow(dead_code)]
+    pub(crate) qclass: String,
+    pub(crate) name: String,
+    /// Transport the query came in on, `udp` or `tcp`. DoT and DoH count
+    /// as `tcp` and DoQ as `udp`, for log and dnstap input alike.
+    pub(crate) proto: String,
+    /// Size of the request in bytes.
+    #[allow(dead_code)]
+    pub(crate) size: u32,
+    /// Whether the DNSSEC OK bit was set on the request.
+    #[allow(dead_code)]
+    pub(crate) do_bit: bool,
+    /// Advertised EDNS0 buffer size.
+    #[allow(dead_code)]
+    pub(crate) bufsize: u32,
+    pub(crate) rcode: String,
+    pub(crate) rflags: Vec<String>,
+    /// Size of the response in bytes.
+    #[allow(dead_code)]
+    pub(crate) rsize: u32,
+    pub(crate) duration: Duration,
+    /// Answer section of the response, only known for dnstap input.
+    pub(crate) answers: Option<Vec<AnswerRecord>>,
+    /// When the response was sent, if the source records it.
+    pub(crate) timestamp: Option<DateTime<Utc>>,
+}
+
+/// A resource record from the answer section of a response.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AnswerRecord {
+    pub(crate) rtype: String,
+    pub(crate) data: String,
+}
+
+impl DnsQueryEvent {
+    /// Builds an event from the named groups of a compiled
+    /// [`LogFormat`](crate::log_format::LogFormat). Only `remote`, `name` and
+    /// `rcode` are required, missing fields are filled the way CoreDNS fills
+    /// empty placeholders: `-` for strings and zero for numbers.
+    pub fn from_captures(captures: &Captures<'_>) -> Option<Self> {
+        let field = |name: &str| captures.name(name).map(|m| m.as_str());
+        let text = |name: &str| field(name).unwrap_or("-").to_string();
+        fn number<T: FromStr + Default>(value: Option<&str>) -> Option<T> {
+            value.map_or(Some(T::default()), |v| v.parse().ok())
+        }
+
+        Some(Self {
+            client_ip: parse_ip(field("remote")?)?,
+            client_port: number(field("port"))?,
+            id: number(field("id"))?,
+            qtype: text("type"),
+            qclass: text("class"),
+            name: field("name")?.to_string(),
+            proto: text("proto"),
+            size: number(field("size"))?,
+            do_bit: number(field("do"))?,
+            bufsize: number(field("bufsize"))?,
+            rcode: field("rcode")?.to_string(),
+            rflags: field("rflags")
+                .unwrap_or_default()
+                .split(',')
+                .filter(|flag| !flag.is_empty())
+                .map(str::to_string)
+                .collect(),
+            rsize: number(field("rsize"))?,
+            duration: Duration::try_from_secs_f64(number(
+                field("duration").map(|d| d.trim_end_matches('s')),
+            )?)
+            .ok()?,
+            answers: None,
+            timestamp: None,
+        })
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.rflags.iter().any(|f| f == flag)
+    }
+
+    /// Whether a NOERROR response carried no record of the queried type
+    /// (NODATA), `None` when the answers are not known.
+    pub fn is_empty_answer(&self) -> Option<bool> {
+        let answers = self.answers.as_ref()?;
+        Some(self.rcode == "NOERROR" && !answers.iter().any(|answer| answer.rtype == self.qtype))
+    }
+}
+
+/// Parses an address in its bare or `[addr]` form. IPv4-mapped IPv6
+/// addresses are turned into plain IPv4 so that they match pod IPs.
+fn parse_ip(ip: &str) -> Option<IpAddr> {
+    let ip = ip
+        .strip_prefix('[')
+        .and_then(|ip| ip.strip_suffix(']'))
+        .unwrap_or(ip);
+    ip.parse::<IpAddr>().ok().map(|ip| ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_format::LogFormat;
+
+    fn event(qtype: &str, rcode: &str, answers: Option<&[&str]>) -> DnsQueryEvent {
+        let mut event = LogFormat::compile("{remote} {type} {name} {rcode}")
+            .unwrap()
+            .parse(&format!(
+                "[INFO] 10.244.0.5 {} example.com. {}",
+                qtype, rcode
+            ))
+            .unwrap();
+        event.answers = answers.map(|rtypes| {
+            rtypes
+                .iter()
+                .map(|rtype| AnswerRecord {
+                    rtype: rtype.to_string(),
+                    data: "-".to_string(),
+                })
+                .collect()
+        });
+        event
+    }
+
+    #[test]
+    fn empty_answers_are_unknown_without_the_answer_section() {
+        assert_eq!(event("AAAA", "NOERROR", None).is_empty_answer(), None);
+    }
+
+    #[test]
+    fn finds_noerror_responses_without_the_queried_type() {
+        assert_eq!(
+            event("AAAA", "NOERROR", Some(&[])).is_empty_answer(),
+            Some(true)
+        );
+        // A CNAME chain ending without an address
+        assert_eq!(
+            event("AAAA", "NOERROR", Some(&["CNAME"])).is_empty_answer(),
+            Some(true)
+        );
+        assert_eq!(
+            event("AAAA", "NOERROR", Some(&["CNAME", "AAAA"])).is_empty_answer(),
+            Some(false)
+        );
+        // Failures are counted by their rcode instead
+        assert_eq!(
+            event("AAAA", "NXDOMAIN", Some(&[])).is_empty_answer(),
+            Some(false)
+        );
+    }
+}
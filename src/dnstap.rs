@@ -0,0 +1,500 @@
+use anyhow::{Result, bail};
+use hickory_proto::op::{Edns, Message, MessageType};
+use k8s_openapi::chrono::{DateTime, Utc};
+use prost::Message as _;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::unix::fs::FileTypeExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::mpsc;
+
+use crate::dns_event::{AnswerRecord, DnsQueryEvent};
+
+/// Content type negotiated on Frame Streams connections.
+const CONTENT_TYPE: &[u8] = b"protobuf:dnstap.Dnstap";
+/// Upper bound for a single frame, CoreDNS messages are well below it.
+const MAX_FRAME_SIZE: u32 = 1 << 20;
+
+// Frame Streams control frame types and fields.
+const CONTROL_ACCEPT: u32 = 0x01;
+const CONTROL_START: u32 = 0x02;
+const CONTROL_STOP: u32 = 0x03;
+const CONTROL_READY: u32 = 0x04;
+const CONTROL_FINISH: u32 = 0x05;
+const FIELD_CONTENT_TYPE: u32 = 0x01;
+
+// dnstap `Message.Type` of the response CoreDNS sends back to the client.
+const CLIENT_RESPONSE: i32 = 6;
+
+/// The subset of dnstap.proto (https://github.com/dnstap/dnstap.pb) needed
+/// to rebuild a query event.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Dnstap {
+        #[prost(bytes = "vec", optional, tag = "1")]
+        pub identity: Option<Vec<u8>>,
+        #[prost(message, optional, tag = "14")]
+        pub message: Option<Message>,
+        #[prost(int32, optional, tag = "15")]
+        pub r#type: Option<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Message {
+        #[prost(int32, optional, tag = "1")]
+        pub r#type: Option<i32>,
+        #[prost(int32, optional, tag = "3")]
+        pub socket_protocol: Option<i32>,
+        #[prost(bytes = "vec", optional, tag = "4")]
+        pub query_address: Option<Vec<u8>>,
+        #[prost(uint32, optional, tag = "6")]
+        pub query_port: Option<u32>,
+        #[prost(uint64, optional, tag = "8")]
+        pub query_time_sec: Option<u64>,
+        #[prost(fixed32, optional, tag = "9")]
+        pub query_time_nsec: Option<u32>,
+        #[prost(bytes = "vec", optional, tag = "10")]
+        pub query_message: Option<Vec<u8>>,
+        #[prost(uint64, optional, tag = "12")]
+        pub response_time_sec: Option<u64>,
+        #[prost(fixed32, optional, tag = "13")]
+        pub response_time_nsec: Option<u32>,
+        #[prost(bytes = "vec", optional, tag = "14")]
+        pub response_message: Option<Vec<u8>>,
+    }
+}
+
+/// A query event decoded from a dnstap frame.
+pub struct Tap {
+    /// Identity of the sender, the hostname (pod name) for CoreDNS.
+    pub identity: Arc<str>,
+    pub event: DnsQueryEvent,
+}
+
+enum Frame {
+    Data(Vec<u8>),
+    Control(u32),
+}
+
+/// Listens for CoreDNS `dnstap` connections on `unix:///path` or
+/// `tcp://host:port` and decodes the client responses they carry.
+pub async fn listen(addr: &str) -> Result<mpsc::Receiver<Tap>> {
+    let (sender, receiver) = mpsc::channel(1024);
+
+    if let Some(addr) = addr.strip_prefix("tcp://") {
+        let listener = TcpListener::bind(addr).await?;
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        log::info!("dnstap connection from {}", peer);
+                        tokio::spawn(serve(stream, sender.clone()));
+                    }
+                    Err(err) => log::error!("Failed to accept dnstap connection: {}", err),
+                }
+            }
+        });
+    } else if let Some(path) = addr.strip_prefix("unix://") {
+        // A socket left behind by a previous run would make bind fail.
+        if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        let path = path.to_string();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        log::info!("dnstap connection on {}", path);
+                        tokio::spawn(serve(stream, sender.clone()));
+                    }
+                    Err(err) => log::error!("Failed to accept dnstap connection: {}", err),
+                }
+            }
+        });
+    } else {
+        bail!(
+            "Unsupported dnstap address {}, expected unix:///path or tcp://host:port",
+            addr
+        );
+    }
+
+    Ok(receiver)
+}
+
+/// Reads a dnstap file as written by `dnstap -w` or `coredns`'s file output.
+pub async fn read_file(path: &str) -> Result<mpsc::Receiver<Tap>> {
+    let mut file = BufReader::new(tokio::fs::File::open(path).await?);
+    let (sender, receiver) = mpsc::channel(1024);
+
+    tokio::spawn(async move {
+        if let Err(err) = read_taps(&mut file, &sender).await {
+            log::error!("Failed to read dnstap file: {}", err);
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Runs the bidirectional Frame Streams handshake, then forwards the data
+/// frames until the sender stops.
+async fn serve<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, sender: mpsc::Sender<Tap>) {
+    let result = async {
+        match read_frame(&mut stream).await? {
+            Some(Frame::Control(CONTROL_READY)) => {
+                write_control(&mut stream, CONTROL_ACCEPT, Some(CONTENT_TYPE)).await?
+            }
+            _ => bail!("Expected a READY frame"),
+        }
+        read_taps(&mut stream, &sender).await?;
+        write_control(&mut stream, CONTROL_FINISH, None).await
+    }
+    .await;
+
+    if let Err(err) = result {
+        log::error!("dnstap connection failed: {}", err);
+    }
+}
+
+async fn read_taps<R: AsyncRead + Unpin>(reader: &mut R, sender: &mpsc::Sender<Tap>) -> Result<()> {
+    match read_frame(reader).await? {
+        Some(Frame::Control(CONTROL_START)) => {}
+        _ => bail!("Expected a START frame"),
+    }
+
+    while let Some(frame) = read_frame(reader).await? {
+        match frame {
+            Frame::Data(data) => {
+                if let Some(tap) = decode(&data)
+                    && sender.send(tap).await.is_err()
+                {
+                    break;
+                }
+            }
+            Frame::Control(CONTROL_STOP) => break,
+            Frame::Control(other) => log::warn!("Unexpected dnstap control frame {}", other),
+        }
+    }
+
+    Ok(())
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Frame>> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len,
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    // A zero length escapes a control frame, which carries its own length.
+    let (len, control) = match len {
+        0 => (reader.read_u32().await?, true),
+        len => (len, false),
+    };
+    if len > MAX_FRAME_SIZE {
+        bail!("dnstap frame of {} bytes is too large", len);
+    }
+
+    let mut buf = vec![0; len as usize];
+    reader.read_exact(&mut buf).await?;
+
+    if !control {
+        return Ok(Some(Frame::Data(buf)));
+    }
+    match buf.first_chunk::<4>() {
+        // Content type fields are ignored, CoreDNS only speaks dnstap.
+        Some(control_type) => Ok(Some(Frame::Control(u32::from_be_bytes(*control_type)))),
+        None => bail!("Truncated dnstap control frame"),
+    }
+}
+
+async fn write_control<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    control_type: u32,
+    content_type: Option<&[u8]>,
+) -> Result<()> {
+    let mut frame = control_type.to_be_bytes().to_vec();
+    if let Some(content_type) = content_type {
+        frame.extend(FIELD_CONTENT_TYPE.to_be_bytes());
+        frame.extend((content_type.len() as u32).to_be_bytes());
+        frame.extend(content_type);
+    }
+
+    writer.write_u32(0).await?;
+    writer.write_u32(frame.len() as u32).await?;
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Turns a dnstap `CLIENT_RESPONSE` into a query event. CoreDNS has to be
+/// configured with `dnstap <endpoint> full` for the messages to be included.
+fn decode(frame: &[u8]) -> Option<Tap> {
+    let tap = proto::Dnstap::decode(frame).ok()?;
+    let message = tap.message?;
+    if message.r#type != Some(CLIENT_RESPONSE) {
+        return None;
+    }
+
+    let response_bytes = message.response_message.as_deref()?;
+    let response = Message::from_vec(response_bytes).ok()?;
+    if response.message_type() != MessageType::Response {
+        return None;
+    }
+    let query = message
+        .query_message
+        .as_deref()
+        .and_then(|query| Message::from_vec(query).ok());
+    let question = response.queries().first()?;
+    let edns = query
+        .as_ref()
+        .and_then(|query| query.extensions().as_ref())
+        .or(response.extensions().as_ref());
+
+    let client_ip = match message.query_address.as_deref()? {
+        &[a, b, c, d] => IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+        addr => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(addr).ok()?)).to_canonical(),
+    };
+    let query_time = timestamp(message.query_time_sec, message.query_time_nsec);
+    let response_time = timestamp(message.response_time_sec, message.response_time_nsec);
+    let duration = match (query_time, response_time) {
+        (Some(query), Some(response)) => (response - query).to_std().unwrap_or_default(),
+        _ => Duration::ZERO,
+    };
+
+    let event = DnsQueryEvent {
+        client_ip,
+        client_port: message.query_port.unwrap_or_default() as u16,
+        id: response.id(),
+        qtype: question.query_type().to_string(),
+        qclass: question.query_class().to_string(),
+        name: question.name().to_string(),
+        proto: socket_protocol(message.socket_protocol).to_string(),
+        size: message.query_message.as_ref().map_or(0, Vec::len) as u32,
+        do_bit: edns.is_some_and(|edns| edns.flags().dnssec_ok),
+        bufsize: edns.map_or(0, Edns::max_payload).into(),
+        rcode: rcode_name(response.response_code().into()),
+        rflags: response_flags(&response),
+        rsize: response_bytes.len() as u32,
+        duration,
+        answers: Some(
+            response
+                .answers()
+                .iter()
+                .map(|record| AnswerRecord {
+                    rtype: record.record_type().to_string(),
+                    data: record.data().to_string(),
+                })
+                .collect(),
+        ),
+        timestamp: response_time,
+    };
+
+    Some(Tap {
+        identity: Arc::from(
+            String::from_utf8_lossy(tap.identity.as_deref().unwrap_or_default()).as_ref(),
+        ),
+        event,
+    })
+}
+
+fn timestamp(sec: Option<u64>, nsec: Option<u32>) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(sec?.try_into().ok()?, nsec.unwrap_or_default())
+}
+
+/// dnstap `SocketProtocol` mapped to the transport CoreDNS logs as
+/// `{proto}`, so both inputs count queries under the same labels.
+fn socket_protocol(protocol: Option<i32>) -> &'static str {
+    match protocol {
+        // UDP, DNSCrypt over UDP and DoQ
+        Some(1 | 5 | 7) => "udp",
+        // TCP, DoT, DoH and DNSCrypt over TCP
+        Some(2 | 3 | 4 | 6) => "tcp",
+        _ => "-",
+    }
+}
+
+/// Response codes spelled the way CoreDNS logs them.
+fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        6 => "YXDOMAIN".to_string(),
+        7 => "YXRRSET".to_string(),
+        8 => "NXRRSET".to_string(),
+        9 => "NOTAUTH".to_string(),
+        10 => "NOTZONE".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Header flags in the order of CoreDNS's `{>rflags}`.
+fn response_flags(response: &Message) -> Vec<String> {
+    let header = response.header();
+    [
+        ("qr", true),
+        ("aa", header.authoritative()),
+        ("tc", header.truncated()),
+        ("rd", header.recursion_desired()),
+        ("ra", header.recursion_available()),
+        ("ad", header.authentic_data()),
+        ("cd", header.checking_disabled()),
+    ]
+    .into_iter()
+    .filter(|(_, set)| *set)
+    .map(|(flag, _)| flag.to_string())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::op::{Query, ResponseCode};
+    use hickory_proto::rr::{Name, RData, Record, RecordType, rdata::A};
+    use std::str::FromStr;
+
+    fn response_frame(message_type: i32) -> Vec<u8> {
+        let name = Name::from_str("web.shop.svc.cluster.local.").unwrap();
+        let mut response = Message::new();
+        response
+            .set_id(4021)
+            .set_message_type(MessageType::Response)
+            .set_recursion_desired(true)
+            .set_recursion_available(true)
+            .set_response_code(ResponseCode::NoError)
+            .add_query(Query::query(name.clone(), RecordType::A))
+            .add_answer(Record::from_rdata(name, 5, RData::A(A::new(10, 96, 0, 10))));
+
+        proto::Dnstap {
+            identity: Some(b"coredns-1".to_vec()),
+            r#type: Some(1),
+            message: Some(proto::Message {
+                r#type: Some(message_type),
+                socket_protocol: Some(2),
+                query_address: Some(vec![10, 244, 0, 5]),
+                query_port: Some(43211),
+                query_time_sec: Some(1_700_000_000),
+                query_time_nsec: Some(0),
+                query_message: None,
+                response_time_sec: Some(1_700_000_000),
+                response_time_nsec: Some(250_000),
+                response_message: Some(response.to_vec().unwrap()),
+            }),
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn decodes_client_responses() {
+        let tap = decode(&response_frame(CLIENT_RESPONSE)).unwrap();
+        assert_eq!(&*tap.identity, "coredns-1");
+
+        let event = tap.event;
+        assert_eq!(event.client_ip, IpAddr::V4(Ipv4Addr::new(10, 244, 0, 5)));
+        assert_eq!(event.client_port, 43211);
+        assert_eq!(event.id, 4021);
+        assert_eq!(event.qtype, "A");
+        assert_eq!(event.qclass, "IN");
+        assert_eq!(event.name, "web.shop.svc.cluster.local.");
+        assert_eq!(event.proto, "tcp");
+        assert_eq!(event.rcode, "NOERROR");
+        assert_eq!(event.rflags, ["qr", "rd", "ra"]);
+        assert_eq!(event.duration, Duration::from_micros(250));
+        assert_eq!(
+            event.answers.unwrap(),
+            [AnswerRecord {
+                rtype: "A".to_string(),
+                data: "10.96.0.10".to_string(),
+            }]
+        );
+        assert_eq!(
+            event.timestamp,
+            DateTime::from_timestamp(1_700_000_000, 250_000)
+        );
+    }
+
+    #[test]
+    fn skips_other_messages() {
+        // CLIENT_QUERY
+        assert!(decode(&response_frame(5)).is_none());
+        assert!(decode(b"not a dnstap frame").is_none());
+    }
+
+    #[test]
+    fn labels_protocols_like_coredns_logs() {
+        assert_eq!(socket_protocol(Some(1)), "udp");
+        assert_eq!(socket_protocol(Some(7)), "udp");
+        for dot_doh_or_tcp in [2, 3, 4] {
+            assert_eq!(socket_protocol(Some(dot_doh_or_tcp)), "tcp");
+        }
+        assert_eq!(socket_protocol(None), "-");
+    }
+
+    #[tokio::test]
+    async fn runs_the_frame_streams_handshake() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let (sender, mut receiver) = mpsc::channel(1);
+        let server = tokio::spawn(serve(server, sender));
+
+        write_control(&mut client, CONTROL_READY, Some(CONTENT_TYPE))
+            .await
+            .unwrap();
+        assert!(matches!(
+            read_frame(&mut client).await.unwrap(),
+            Some(Frame::Control(CONTROL_ACCEPT))
+        ));
+
+        write_control(&mut client, CONTROL_START, Some(CONTENT_TYPE))
+            .await
+            .unwrap();
+        let frame = response_frame(CLIENT_RESPONSE);
+        client.write_u32(frame.len() as u32).await.unwrap();
+        client.write_all(&frame).await.unwrap();
+        let tap = receiver.recv().await.unwrap();
+        assert_eq!(tap.event.name, "web.shop.svc.cluster.local.");
+
+        write_control(&mut client, CONTROL_STOP, None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            read_frame(&mut client).await.unwrap(),
+            Some(Frame::Control(CONTROL_FINISH))
+        ));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_streams_without_ready() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let (sender, mut receiver) = mpsc::channel(1);
+        let server = tokio::spawn(serve(server, sender));
+
+        write_control(&mut client, CONTROL_START, Some(CONTENT_TYPE))
+            .await
+            .unwrap();
+        server.await.unwrap();
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_schemes() {
+        assert!(listen("udp://127.0.0.1:6000").await.is_err());
+        assert!(listen("/tmp/dnstap.sock").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn keeps_files_in_the_way_of_the_socket() {
+        let path = std::env::temp_dir().join(format!("scope-rs-dnstap-{}", std::process::id()));
+        std::fs::write(&path, "not a socket").unwrap();
+        let addr = format!("unix://{}", path.display());
+        assert!(listen(&addr).await.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,667 @@
+use anyhow::Result;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client};
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::config::{CONFIG, ExternalAggregation};
+use crate::dns_event::DnsQueryEvent;
+use crate::psl;
+use crate::suffixes;
+
+/// Splits query names into internal names, served from one of the cluster
+/// zones, and external ones.
+#[derive(Debug, Clone)]
+pub struct DomainClassifier {
+    internal_zones: Vec<String>,
+    external_aggregation: ExternalAggregation,
+}
+
+impl DomainClassifier {
+    pub fn new(
+        zones: impl IntoIterator<Item = String>,
+        external_aggregation: ExternalAggregation,
+    ) -> Self {
+        let mut internal_zones: Vec<String> = Vec::new();
+        for zone in zones {
+            let zone = zone.trim_matches('.').to_lowercase();
+            if !zone.is_empty() && !internal_zones.contains(&zone) {
+                internal_zones.push(zone);
+            }
+        }
+        Self {
+            internal_zones,
+            external_aggregation,
+        }
+    }
+
+    pub fn internal_zones(&self) -> &[String] {
+        &self.internal_zones
+    }
+
+    /// Returns the zone a name belongs to, if it is internal.
+    fn internal_zone(&self, query: &str) -> Option<&str> {
+        self.internal_zones
+            .iter()
+            .find(|zone| {
+                let Some(start) = query.len().checked_sub(zone.len() + 1) else {
+                    return false;
+                };
+                query.get(start..start + 1) == Some(".")
+                    && query[start + 1..].eq_ignore_ascii_case(zone)
+            })
+            .map(String::as_str)
+    }
+
+    /// The record a name in one of the internal zones is for, if it is one
+    /// the `kubernetes` plugin serves.
+    pub fn internal_name(&self, name: &str) -> Option<InternalName> {
+        let zone = self.internal_zone(name)?;
+        InternalName::parse(&name[..name.len() - zone.len() - 1])
+    }
+
+    /// Classifies the query name. Failed queries are kept so their rcodes
+    /// can be counted, without the TLD check: names that do not exist are
+    /// exactly what they are about.
+    pub fn extract_domain_name(&self, event: &DnsQueryEvent) -> Option<DomainName> {
+        let query = event.name.trim_end_matches('.');
+
+        if let Some(zone) = self.internal_zone(query) {
+            match InternalName::parse(&query[..query.len() - zone.len() - 1]) {
+                Some(name) => Some(DomainName::Internal(name)),
+                None if event.rcode != "NOERROR" => Some(DomainName::Internal(
+                    InternalName::Other(query.to_ascii_lowercase()),
+                )),
+                None => None,
+            }
+        } else if let Some(ip) = parse_reverse(query) {
+            Some(DomainName::Reverse(ReverseName { ip, owner: None }))
+        } else if query.is_empty() || is_reverse_zone(query) {
+            // Partial reverse names (`10.in-addr.arpa`) are zone lookups,
+            // not queries for a host outside the cluster.
+            None
+        } else if event.rcode != "NOERROR"
+            || query
+                .rsplit_once('.')
+                .is_some_and(|(_, tld)| suffixes::tlds().contains(tld))
+        {
+            Some(DomainName::External(self.external_key(query)))
+        } else {
+            None
+        }
+    }
+
+    /// Name an external query is grouped under.
+    fn external_key(&self, query: &str) -> String {
+        match self.external_aggregation {
+            ExternalAggregation::Fqdn => query.to_ascii_lowercase(),
+            ExternalAggregation::Registrable => psl::registrable_domain(query)
+                .unwrap_or(query)
+                .to_ascii_lowercase(),
+            ExternalAggregation::Suffix => psl::public_suffix(query).to_ascii_lowercase(),
+        }
+    }
+}
+
+pub enum DomainName {
+    Internal(InternalName),
+    External(String),
+    /// A PTR lookup under `in-addr.arpa` or `ip6.arpa`.
+    Reverse(ReverseName),
+}
+
+/// The address a reverse lookup asks about, and what it belongs to once
+/// resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ReverseName {
+    pub(crate) ip: IpAddr,
+    pub(crate) owner: Option<IpOwner>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IpOwner {
+    Pod(String),
+    /// A Service ClusterIP.
+    Service(ServiceId),
+    Node(String),
+}
+
+impl ReverseName {
+    /// The name with the pod owning the address renamed, to group it the
+    /// way querying pods are.
+    pub fn map_pod(&self, rename: impl Fn(&str) -> String) -> Self {
+        let owner = match &self.owner {
+            Some(IpOwner::Pod(pod)) => Some(IpOwner::Pod(rename(pod))),
+            owner => owner.clone(),
+        };
+        Self { ip: self.ip, owner }
+    }
+}
+
+impl fmt::Display for ReverseName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.owner {
+            Some(owner) => write!(f, "{} ({})", self.ip, owner),
+            None => self.ip.fmt(f),
+        }
+    }
+}
+
+impl Serialize for ReverseName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for IpOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pod(pod) => write!(f, "pod {}", pod),
+            Self::Service(service) => write!(f, "service {}", service),
+            Self::Node(node) => write!(f, "node {}", node),
+        }
+    }
+}
+
+/// The kinds of records the `kubernetes` plugin serves.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InternalName {
+    /// `<service>.<ns>.svc`
+    Service(ServiceId),
+    /// `_<port>._<proto>.<service>.<ns>.svc`
+    Srv(ServiceId),
+    /// A member of a headless service (StatefulSet pods for example),
+    /// `<hostname>.<service>.<ns>.svc`.
+    Endpoint {
+        hostname: String,
+        service: ServiceId,
+        /// `<namespace>/<name>` of the pod, once resolved.
+        pod: Option<String>,
+    },
+    /// `<dashed-ip>.<ns>.pod`
+    Pod {
+        ip: IpAddr,
+        namespace: String,
+        /// `<namespace>/<name>` of the pod, once resolved.
+        pod: Option<String>,
+    },
+    /// A failed query in an internal zone that is none of the above, a
+    /// name missing its `.svc` for example. Kept whole, zone included.
+    Other(String),
+}
+
+impl InternalName {
+    /// Parses the part of a name in front of `.<zone>`.
+    fn parse(name: &str) -> Option<Self> {
+        if let Some(name) = strip_suffix_ignore_case(name, ".pod") {
+            let (ip, namespace) = name.split_once('.')?;
+            if namespace.contains('.') {
+                return None;
+            }
+            return Some(Self::Pod {
+                ip: parse_dashed_ip(ip)?,
+                namespace: namespace.to_ascii_lowercase(),
+                pod: None,
+            });
+        }
+
+        // Labels from the right, a fifth one means the name is too long.
+        let mut labels = strip_suffix_ignore_case(name, ".svc")?.rsplitn(5, '.');
+        let namespace = labels.next()?;
+        let service = labels.next()?;
+        match (labels.next(), labels.next(), labels.next()) {
+            (None, _, _) => Some(Self::Service(ServiceId::new(service, namespace))),
+            (Some(hostname), None, _) => Some(Self::Endpoint {
+                hostname: hostname.to_ascii_lowercase(),
+                service: ServiceId::new(service, namespace),
+                pod: None,
+            }),
+            (Some(proto), Some(port), None) if port.starts_with('_') && proto.starts_with('_') => {
+                Some(Self::Srv(ServiceId {
+                    port: Some(port[1..].to_ascii_lowercase()),
+                    proto: Some(proto[1..].to_ascii_lowercase()),
+                    ..ServiceId::new(service, namespace)
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// The name with the pod it points at renamed, to group it the way
+    /// querying pods are.
+    pub fn map_pod(&self, rename: impl Fn(&str) -> String) -> Self {
+        let mut name = self.clone();
+        if let Self::Endpoint { pod, .. } | Self::Pod { pod, .. } = &mut name {
+            *pod = pod.as_deref().map(rename);
+        }
+        name
+    }
+
+    /// The pod the name points at, once resolved.
+    pub fn target_pod(&self) -> Option<&str> {
+        match self {
+            Self::Endpoint { pod, .. } | Self::Pod { pod, .. } => pod.as_deref(),
+            Self::Service(_) | Self::Srv(_) | Self::Other(_) => None,
+        }
+    }
+}
+
+/// The queried record, followed by the pod it resolved to, so that records
+/// pointing at the same pod stay apart: `web-0.web.shop (pod shop/web-0)`.
+impl fmt::Display for InternalName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Service(service) | Self::Srv(service) => service.fmt(f)?,
+            Self::Endpoint {
+                hostname, service, ..
+            } => write!(f, "{}.{}", hostname, service)?,
+            Self::Pod { ip, namespace, .. } => write!(f, "{}.{}.pod", ip, namespace)?,
+            Self::Other(name) => name.fmt(f)?,
+        }
+        match self.target_pod() {
+            Some(pod) => write!(f, " (pod {})", pod),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Serialize for InternalName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Identity of a service inside the cluster. SRV names (`_port._proto.svc.ns`)
+/// also carry the port name and protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ServiceId {
+    pub(crate) service: String,
+    pub(crate) namespace: String,
+    pub(crate) port: Option<String>,
+    pub(crate) proto: Option<String>,
+}
+
+impl ServiceId {
+    pub fn new(service: &str, namespace: &str) -> Self {
+        Self {
+            service: service.to_ascii_lowercase(),
+            namespace: namespace.to_ascii_lowercase(),
+            port: None,
+            proto: None,
+        }
+    }
+}
+
+impl fmt::Display for ServiceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let (Some(port), Some(proto)) = (&self.port, &self.proto) {
+            write!(f, "_{}._{}.", port, proto)?;
+        }
+        write!(f, "{}.{}", self.service, self.namespace)
+    }
+}
+
+fn strip_suffix_ignore_case<'a>(name: &'a str, suffix: &str) -> Option<&'a str> {
+    let start = name.len().checked_sub(suffix.len())?;
+    name.get(start..)
+        .is_some_and(|tail| tail.eq_ignore_ascii_case(suffix))
+        .then(|| &name[..start])
+}
+
+/// Decodes the address of a full `in-addr.arpa` or `ip6.arpa` name,
+/// `5.0.244.10.in-addr.arpa` or 32 reversed nibbles for IPv6.
+pub fn parse_reverse(name: &str) -> Option<IpAddr> {
+    if let Some(labels) = strip_suffix_ignore_case(name, ".in-addr.arpa") {
+        let mut octets = [0u8; 4];
+        let mut labels = labels.split('.');
+        for octet in octets.iter_mut().rev() {
+            *octet = labels.next()?.parse().ok()?;
+        }
+        return labels
+            .next()
+            .is_none()
+            .then_some(IpAddr::V4(Ipv4Addr::from(octets)));
+    }
+
+    let labels = strip_suffix_ignore_case(name, ".ip6.arpa")?;
+    let mut address = 0u128;
+    let mut nibbles = 0;
+    for label in labels.rsplit('.') {
+        if label.len() != 1 || nibbles == 32 {
+            return None;
+        }
+        address = address << 4 | u128::from_str_radix(label, 16).ok()?;
+        nibbles += 1;
+    }
+    (nibbles == 32).then_some(IpAddr::V6(Ipv6Addr::from(address)).to_canonical())
+}
+
+/// Whether the name is under `in-addr.arpa` or `ip6.arpa`, or one of them.
+fn is_reverse_zone(name: &str) -> bool {
+    [".in-addr.arpa", ".ip6.arpa"].iter().any(|zone| {
+        name.eq_ignore_ascii_case(&zone[1..]) || strip_suffix_ignore_case(name, zone).is_some()
+    })
+}
+
+/// Parses the dashed form of an address used in pod records,
+/// `10-0-0-5` or `fd00-10--5`.
+pub fn parse_dashed_ip(label: &str) -> Option<IpAddr> {
+    if label.matches('-').count() == 3
+        && let Ok(ip) = label.replace('-', ".").parse::<Ipv4Addr>()
+    {
+        return Some(IpAddr::V4(ip));
+    }
+    label
+        .replace('-', ":")
+        .parse::<Ipv6Addr>()
+        .ok()
+        .map(IpAddr::V6)
+}
+
+/// Reads the zones served by the `kubernetes` (and `multicluster`) plugins
+/// from the CoreDNS Corefile ConfigMap.
+pub async fn discover_zones(client: &Client) -> Result<Vec<String>> {
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), &CONFIG.coredns_ns);
+    let config_map = config_maps.get(&CONFIG.coredns_configmap).await?;
+    let corefile = config_map
+        .data
+        .as_ref()
+        .and_then(|data| data.get("Corefile"))
+        .ok_or_else(|| anyhow::anyhow!("ConfigMap {} has no Corefile", CONFIG.coredns_configmap))?;
+
+    Ok(corefile_zones(corefile))
+}
+
+/// Zones listed on `kubernetes` and `multicluster` plugin lines, or the
+/// zones of their server block when the line lists none, without the
+/// reverse zones the plugin usually serves too.
+fn corefile_zones(corefile: &str) -> Vec<String> {
+    let mut zones = Vec::new();
+    let mut server_zones = Vec::new();
+    let mut depth = 0usize;
+    // Tokens of the directive being read, until a brace or the end of the line.
+    let mut directive: Vec<String> = Vec::new();
+    let mut end_directive = |directive: &mut Vec<String>, depth: usize, opens: bool| {
+        match directive.first().map(String::as_str) {
+            Some(_) if depth == 0 && opens => {
+                server_zones = directive.iter().map(|key| server_zone(key)).collect();
+            }
+            Some("kubernetes" | "multicluster") if depth == 1 => {
+                if directive.len() == 1 {
+                    zones.extend(server_zones.iter().cloned());
+                } else {
+                    zones.extend(directive.drain(1..));
+                }
+            }
+            _ => {}
+        }
+        directive.clear();
+    };
+    for line in corefile.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let line = line.replace('{', " { ").replace('}', " } ");
+        for token in line.split_whitespace() {
+            match token {
+                "{" => {
+                    end_directive(&mut directive, depth, true);
+                    depth += 1;
+                }
+                "}" => {
+                    end_directive(&mut directive, depth, false);
+                    depth = depth.saturating_sub(1);
+                }
+                token => directive.push(token.to_string()),
+            }
+        }
+        end_directive(&mut directive, depth, false);
+    }
+    zones
+        .into_iter()
+        .map(|zone| zone.trim_end_matches('.').to_lowercase())
+        .filter(|zone| !zone.is_empty() && !zone.contains('/'))
+        .filter(|zone| !zone.ends_with("in-addr.arpa") && !zone.ends_with("ip6.arpa"))
+        .collect()
+}
+
+/// The zone of a server block key, `dns://cluster.local:53` for example.
+fn server_zone(key: &str) -> String {
+    let zone = key.split_once("://").map_or(key, |(_, zone)| zone);
+    zone.rsplit_once(':')
+        .map_or(zone, |(zone, _)| zone)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_format::LogFormat;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn displays_records_with_their_target_pod() {
+        let member = |namespace: &str, pod: Option<&str>| InternalName::Endpoint {
+            hostname: "web-0".to_string(),
+            service: ServiceId::new("web", namespace),
+            pod: pod.map(str::to_string),
+        };
+        assert_eq!(member("a", None).to_string(), "web-0.web.a");
+        assert_eq!(
+            member("a", Some("a/web-0")).to_string(),
+            "web-0.web.a (pod a/web-0)"
+        );
+        assert_ne!(
+            member("a", Some("a/web-0")).to_string(),
+            member("b", Some("b/web-0")).to_string()
+        );
+
+        let pod_ip = InternalName::Pod {
+            ip: ip("10.0.0.5"),
+            namespace: "a".to_string(),
+            pod: Some("a/web-0".to_string()),
+        };
+        assert_eq!(pod_ip.to_string(), "10.0.0.5.a.pod (pod a/web-0)");
+    }
+
+    #[test]
+    fn parses_services_folding_case() {
+        assert_eq!(
+            InternalName::parse("Web.Shop.SVC"),
+            Some(InternalName::Service(ServiceId::new("web", "shop")))
+        );
+        assert_eq!(InternalName::parse("shop.svc"), None);
+        assert_eq!(InternalName::parse("web.shop"), None);
+    }
+
+    #[test]
+    fn parses_srv_names() {
+        assert_eq!(
+            InternalName::parse("_HTTP._tcp.web.shop.svc"),
+            Some(InternalName::Srv(ServiceId {
+                port: Some("http".to_string()),
+                proto: Some("tcp".to_string()),
+                ..ServiceId::new("web", "shop")
+            }))
+        );
+        // Four labels are only SRV names with both underscores
+        assert_eq!(InternalName::parse("a.web-0.web.shop.svc"), None);
+        assert_eq!(InternalName::parse("_http.web-0.web.shop.svc"), None);
+        assert_eq!(InternalName::parse("_http._tcp.web-0.web.shop.svc"), None);
+    }
+
+    #[test]
+    fn parses_headless_endpoints() {
+        assert_eq!(
+            InternalName::parse("Web-0.web.shop.svc"),
+            Some(InternalName::Endpoint {
+                hostname: "web-0".to_string(),
+                service: ServiceId::new("web", "shop"),
+                pod: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_pod_records() {
+        let pod = |ip: &str| InternalName::Pod {
+            ip: ip.parse().unwrap(),
+            namespace: "shop".to_string(),
+            pod: None,
+        };
+        assert_eq!(
+            InternalName::parse("10-244-0-5.Shop.pod"),
+            Some(pod("10.244.0.5"))
+        );
+        assert_eq!(
+            InternalName::parse("fd00-10-244--5.shop.pod"),
+            Some(pod("fd00:10:244::5"))
+        );
+        assert_eq!(InternalName::parse("10-244-0-5.a.shop.pod"), None);
+        assert_eq!(InternalName::parse("web.shop.pod"), None);
+    }
+
+    #[test]
+    fn parses_dashed_addresses() {
+        assert_eq!(parse_dashed_ip("10-0-0-5"), Some(ip("10.0.0.5")));
+        assert_eq!(parse_dashed_ip("fd00-10--5"), Some(ip("fd00:10::5")));
+        assert_eq!(parse_dashed_ip("10-0-0"), None);
+        assert_eq!(parse_dashed_ip("10-0-0-256"), None);
+        assert_eq!(parse_dashed_ip("web-0"), None);
+    }
+
+    fn classify(classifier: &DomainClassifier, name: &str, rcode: &str) -> Option<String> {
+        let event = LogFormat::compile("{remote} {name} {rcode}")
+            .unwrap()
+            .parse(&format!("[INFO] 10.244.0.5 {} {}", name, rcode))
+            .unwrap();
+        match classifier.extract_domain_name(&event)? {
+            DomainName::Internal(name) => Some(format!("internal {}", name)),
+            DomainName::External(name) => Some(format!("external {}", name)),
+            DomainName::Reverse(name) => Some(format!("reverse {}", name)),
+        }
+    }
+
+    #[test]
+    fn keeps_failed_queries_outside_the_record_grammar() {
+        let classifier =
+            DomainClassifier::new(["cluster.local".to_string()], ExternalAggregation::Fqdn);
+        assert_eq!(
+            classify(&classifier, "redis.shop.cluster.local.", "NXDOMAIN").as_deref(),
+            Some("internal redis.shop.cluster.local")
+        );
+        assert_eq!(
+            classify(&classifier, "Redis.Shop.cluster.local.", "SERVFAIL").as_deref(),
+            Some("internal redis.shop.cluster.local")
+        );
+        assert_eq!(
+            classify(&classifier, "redis.shop.cluster.local.", "NOERROR"),
+            None
+        );
+        assert_eq!(
+            classify(&classifier, "redis.shop.svc.cluster.local.", "NXDOMAIN").as_deref(),
+            Some("internal redis.shop")
+        );
+    }
+
+    #[test]
+    fn groups_external_names_ignoring_case() {
+        for (aggregation, key) in [
+            (ExternalAggregation::Fqdn, "external www.example.com"),
+            (ExternalAggregation::Registrable, "external example.com"),
+            (ExternalAggregation::Suffix, "external com"),
+        ] {
+            let classifier = DomainClassifier::new(["cluster.local".to_string()], aggregation);
+            for name in ["www.example.com.", "WWW.Example.COM."] {
+                assert_eq!(classify(&classifier, name, "NOERROR").as_deref(), Some(key));
+            }
+        }
+    }
+
+    #[test]
+    fn parses_ipv4_reverse_names() {
+        assert_eq!(
+            parse_reverse("5.0.244.10.in-addr.arpa"),
+            Some(ip("10.244.0.5"))
+        );
+        assert_eq!(
+            parse_reverse("5.0.244.10.IN-ADDR.ARPA"),
+            Some(ip("10.244.0.5"))
+        );
+    }
+
+    #[test]
+    fn parses_ipv6_reverse_names() {
+        let name = "5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.4.4.2.0.0.1.0.0.0.0.d.f.ip6.arpa";
+        assert_eq!(parse_reverse(name), Some(ip("fd00:10:244::5")));
+        // IPv4-mapped addresses come back as IPv4
+        let mapped = "5.0.4.f.0.0.a.0.f.f.f.f.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa";
+        assert_eq!(parse_reverse(mapped), Some(ip("10.0.244.5")));
+    }
+
+    #[test]
+    fn rejects_partial_and_malformed_reverse_names() {
+        assert_eq!(parse_reverse("10.in-addr.arpa"), None);
+        assert_eq!(parse_reverse("0.244.10.in-addr.arpa"), None);
+        assert_eq!(parse_reverse("1.5.0.244.10.in-addr.arpa"), None);
+        assert_eq!(parse_reverse("256.0.244.10.in-addr.arpa"), None);
+        assert_eq!(parse_reverse("d.f.ip6.arpa"), None);
+        let long_label = "50.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.4.4.2.0.0.1.0.0.0.0.d.f.ip6.arpa";
+        assert_eq!(parse_reverse(long_label), None);
+        assert_eq!(parse_reverse("example.com"), None);
+    }
+
+    #[test]
+    fn recognizes_reverse_zones() {
+        assert!(is_reverse_zone("10.in-addr.arpa"));
+        assert!(is_reverse_zone("in-addr.arpa"));
+        assert!(is_reverse_zone("d.f.IP6.ARPA"));
+        assert!(!is_reverse_zone("arpa"));
+        assert!(!is_reverse_zone("notin-addr.arpa"));
+        assert!(!is_reverse_zone("in-addr.arpa.example.com"));
+    }
+
+    const COREFILE: &str = r#"
+.:53 {
+    errors
+    kubernetes cluster.local in-addr.arpa ip6.arpa { # the default zones
+       pods insecure
+       fallthrough in-addr.arpa ip6.arpa
+    }
+    forward . /etc/resolv.conf
+}
+clusterset.local:53 k8s.internal {
+    multicluster
+}
+dns://Example.Internal.:5353 10.0.0.0/8:53 {
+    kubernetes {
+        pods verified
+    }
+}
+"#;
+
+    #[test]
+    fn discovers_zones_from_the_corefile() {
+        assert_eq!(
+            corefile_zones(COREFILE),
+            [
+                "cluster.local",
+                "clusterset.local",
+                "k8s.internal",
+                "example.internal"
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_server_block_zones() {
+        assert_eq!(
+            corefile_zones("cluster.local:53 { kubernetes }"),
+            ["cluster.local"]
+        );
+        // The root zone is everything, not a cluster zone
+        assert!(corefile_zones(".:53 {\n    kubernetes\n}").is_empty());
+    }
+}
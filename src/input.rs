@@ -0,0 +1,208 @@
+use anyhow::Result;
+use flate2::read::MultiGzDecoder;
+use k8s_openapi::chrono::{DateTime, Utc};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant, sleep_until};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Reads the lines of a file, or stdin for `-`, on a blocking thread.
+/// Gzip compressed input is detected from its magic bytes. Files that cannot
+/// be opened or read are reported right away, lines that are not UTF-8 are
+/// logged and skipped.
+pub fn read_lines(input: &str) -> Result<mpsc::Receiver<io::Result<String>>> {
+    let mut reader: BufReader<Box<dyn Read + Send>> = if input == "-" {
+        BufReader::new(Box::new(io::stdin()))
+    } else {
+        BufReader::new(Box::new(File::open(input)?))
+    };
+    if input != "-" {
+        // Directories open fine, only reading them fails.
+        reader.fill_buf()?;
+    }
+
+    let source = input.to_string();
+    let (sender, receiver) = mpsc::channel(1024);
+    tokio::task::spawn_blocking(move || {
+        let gzipped = match reader.fill_buf() {
+            Ok(buf) => buf.starts_with(&GZIP_MAGIC),
+            Err(err) => {
+                let _ = sender.blocking_send(Err(err));
+                return;
+            }
+        };
+        let reader: Box<dyn BufRead> = if gzipped {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        } else {
+            Box::new(reader)
+        };
+
+        for (number, line) in reader.split(b'\n').enumerate() {
+            let line = match line.map(String::from_utf8) {
+                Ok(Ok(line)) => Ok(line),
+                Ok(Err(_)) => {
+                    log::warn!(
+                        "Skipping line {} of {}, it is not UTF-8",
+                        number + 1,
+                        source
+                    );
+                    continue;
+                }
+                Err(err) => Err(err),
+            };
+            let failed = line.is_err();
+            let line = line.map(|mut line| {
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+                line
+            });
+            if sender.blocking_send(line).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Splits the `[pod/<name>/<container>] ` prefix written by
+/// `kubectl logs --prefix` off a line, returning the pod name.
+pub fn split_prefix(line: &str) -> Option<(&str, &str)> {
+    let (prefix, rest) = line.strip_prefix("[pod/")?.split_once("] ")?;
+    let (pod, _container) = prefix.split_once('/')?;
+    Some((pod, rest))
+}
+
+/// Paces replayed lines so that they are processed at the rate they were
+/// logged, sped up by `speed`. Without a speed lines are processed as fast
+/// as they can be read.
+pub struct ReplayClock {
+    speed: Option<f64>,
+    start: Option<(DateTime<Utc>, Instant)>,
+}
+
+impl ReplayClock {
+    pub fn new(speed: Option<f64>) -> Self {
+        Self { speed, start: None }
+    }
+
+    pub async fn wait_until(&mut self, timestamp: DateTime<Utc>) {
+        let Some(speed) = self.speed else {
+            return;
+        };
+        let (first, started) = *self.start.get_or_insert((timestamp, Instant::now()));
+        let Ok(offset) = (timestamp - first).to_std() else {
+            return;
+        };
+        // Slow enough speeds push the line past what an `Instant` can hold.
+        let deadline = Duration::try_from_secs_f64(offset.as_secs_f64() / speed)
+            .ok()
+            .and_then(|delay| started.checked_add(delay));
+        match deadline {
+            Some(deadline) => sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+    use tokio::time::timeout;
+
+    const LINES: &str = "[INFO] first\r\n[INFO] second\n";
+
+    fn temp_file(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("scope-rs-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.display().to_string()
+    }
+
+    async fn collect(path: &str) -> Vec<String> {
+        let mut lines = read_lines(path).unwrap();
+        let mut collected = Vec::new();
+        while let Some(line) = lines.recv().await {
+            collected.push(line.unwrap());
+        }
+        std::fs::remove_file(path).unwrap();
+        collected
+    }
+
+    #[test]
+    fn splits_kubectl_prefixes() {
+        assert_eq!(
+            split_prefix("[pod/coredns-5d78c9869d-abcde/coredns] [INFO] query"),
+            Some(("coredns-5d78c9869d-abcde", "[INFO] query"))
+        );
+        assert_eq!(split_prefix("[INFO] query"), None);
+        assert_eq!(split_prefix("[pod/coredns] [INFO] query"), None);
+    }
+
+    #[tokio::test]
+    async fn reads_plain_files() {
+        let path = temp_file("plain.log", LINES.as_bytes());
+        assert_eq!(collect(&path).await, ["[INFO] first", "[INFO] second"]);
+    }
+
+    #[tokio::test]
+    async fn detects_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(LINES.as_bytes()).unwrap();
+        let path = temp_file("compressed.log.gz", &encoder.finish().unwrap());
+        assert_eq!(collect(&path).await, ["[INFO] first", "[INFO] second"]);
+    }
+
+    #[tokio::test]
+    async fn skips_lines_that_are_not_utf8() {
+        let path = temp_file("binary.log", b"[INFO] first\n\xff\xfe\n[INFO] second\n");
+        assert_eq!(collect(&path).await, ["[INFO] first", "[INFO] second"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_unreadable_input() {
+        assert!(read_lines("/nonexistent/coredns.log").is_err());
+        assert!(read_lines(&std::env::temp_dir().display().to_string()).is_err());
+    }
+
+    fn at(millis: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(1_700_000_000_000 + millis).unwrap()
+    }
+
+    #[tokio::test]
+    async fn replays_at_the_logged_pace() {
+        let mut clock = ReplayClock::new(Some(10.0));
+        let started = Instant::now();
+        clock.wait_until(at(0)).await;
+        clock.wait_until(at(1_000)).await;
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(1), "{:?}", elapsed);
+
+        // Lines out of order do not wait
+        let started = Instant::now();
+        clock.wait_until(at(500)).await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn does_not_wait_without_a_speed() {
+        let mut clock = ReplayClock::new(None);
+        let started = Instant::now();
+        clock.wait_until(at(0)).await;
+        clock.wait_until(at(3_600_000)).await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn waits_forever_past_the_clock_range() {
+        let mut clock = ReplayClock::new(Some(f64::MIN_POSITIVE));
+        clock.wait_until(at(0)).await;
+        let wait = timeout(Duration::from_millis(50), clock.wait_until(at(1_000)));
+        assert!(wait.await.is_err());
+    }
+}
@@ -1,187 +1,1242 @@
-use crate::config::CONFIG;
-use crate::tlds::TLDS;
+use crate::catalog::{DanglingDependency, DanglingReason, ServiceCatalog, Sighting};
+use crate::cidr;
+use crate::config::{CONFIG, Granularity};
+use crate::dns_event::{AnswerRecord, DnsQueryEvent};
+use crate::dnstap::{self, Tap};
+use crate::domain::{self, DomainClassifier, DomainName, InternalName, IpOwner, ReverseName};
+use crate::input::{self, ReplayClock};
+use crate::log_format::LogFormat;
+use crate::pod_index::{ClientKeys, PodIndex};
+use crate::search_path::{Held, Lookup, SearchPathCost, SearchPathTracker};
+use crate::suffixes;
 use anyhow::Result;
-use futures::{AsyncBufReadExt, TryStreamExt};
+use futures::{AsyncBufReadExt, StreamExt, TryStreamExt};
 use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::chrono::{DateTime, Utc};
 use kube::{
-    api::{Api, ListParams, LogParams},
     Client,
+    api::{Api, LogParams},
+    runtime::{
+        WatchStreamExt,
+        watcher::{self, Event, watcher},
+    },
+};
+use serde::{Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, hash_map::Entry},
+    hash::Hash,
+    sync::Arc,
 };
-use regex::Captures;
-use regex::Regex;
-use serde::Serialize;
-use std::{collections::HashMap, sync::Arc};
 use tokio::sync::watch::{Receiver, Sender};
-use tokio::sync::{watch, RwLock};
-use tokio::time::{sleep, Duration};
+use tokio::sync::{Mutex, RwLock, mpsc, watch};
+use tokio::task::AbortHandle;
+use tokio::time::{Duration, Instant, sleep, timeout};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// How long ingestion waits at startup for the first listing of the pods.
+const PODS_READY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Answers kept per edge, names behind CDNs can resolve to many addresses.
+const MAX_ANSWERS: usize = 32;
+/// Names listed per pod in the errors and AAAA views.
+const MAX_FAILING_NAMES: usize = 5;
+
+/// Queries seen from one pod for one name, broken down by the CoreDNS
+/// replica that served them and the transport they used.
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct EdgeStats {
+    pub(crate) queries: u64,
+    pub(crate) replicas: HashMap<String, u64>,
+    pub(crate) protocols: HashMap<String, u64>,
+    pub(crate) rcodes: HashMap<String, u64>,
+    pub(crate) qtypes: HashMap<String, u64>,
+    /// NOERROR responses without a record of the queried type, by query
+    /// type. Only known for dnstap input.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) empty: HashMap<String, u64>,
+    /// Responses with the TC bit set, which make clients retry over TCP.
+    pub(crate) truncated: u64,
+    /// Distinct answer records, only known for dnstap input.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) answers: BTreeSet<AnswerRecord>,
+    /// Search-path expansions collapsed into these queries.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub(crate) expansions: u64,
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+impl EdgeStats {
+    /// Queries answered with anything but NOERROR.
+    pub fn failures(&self) -> u64 {
+        self.rcodes
+            .iter()
+            .filter(|(rcode, _)| *rcode != "NOERROR")
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    fn observe(&mut self, replica: &str, event: &DnsQueryEvent) {
+        self.queries += 1;
+        *self.replicas.entry(replica.to_string()).or_default() += 1;
+        *self.protocols.entry(event.proto.clone()).or_default() += 1;
+        *self.rcodes.entry(event.rcode.clone()).or_default() += 1;
+        *self.qtypes.entry(event.qtype.clone()).or_default() += 1;
+        if event.is_empty_answer() == Some(true) {
+            *self.empty.entry(event.qtype.clone()).or_default() += 1;
+        }
+        if event.has_flag("tc") {
+            self.truncated += 1;
+        }
+        for answer in event.answers.iter().flatten() {
+            if self.answers.len() >= MAX_ANSWERS {
+                break;
+            }
+            self.answers.insert(answer.clone());
+        }
+    }
+
+    fn merge(&mut self, other: &EdgeStats) {
+        self.queries += other.queries;
+        for (counts, other) in [
+            (&mut self.replicas, &other.replicas),
+            (&mut self.protocols, &other.protocols),
+            (&mut self.rcodes, &other.rcodes),
+            (&mut self.qtypes, &other.qtypes),
+            (&mut self.empty, &other.empty),
+        ] {
+            for (key, count) in other {
+                *counts.entry(key.clone()).or_default() += count;
+            }
+        }
+        self.truncated += other.truncated;
+        for answer in &other.answers {
+            if self.answers.len() >= MAX_ANSWERS {
+                break;
+            }
+            self.answers.insert(answer.clone());
+        }
+        self.expansions += other.expansions;
+    }
+}
+
+/// Re-keys a map by querying pod, merging the values that end up together.
+fn regroup<V: Clone>(
+    map: &HashMap<String, V>,
+    key: impl Fn(&str) -> String,
+    merge: impl Fn(&mut V, &V),
+) -> HashMap<String, V> {
+    let mut grouped: HashMap<String, V> = HashMap::new();
+    for (client, value) in map {
+        match grouped.entry(key(client)) {
+            Entry::Occupied(mut entry) => merge(entry.get_mut(), value),
+            Entry::Vacant(entry) => {
+                entry.insert(value.clone());
+            }
+        }
+    }
+    grouped
+}
+
+/// Regroups the querying pods of every name, and the pods the names point
+/// at, merging the names that end up the same.
+fn regroup_edges<K: Clone + Eq + Hash>(
+    map: &EdgeMap<K>,
+    name: impl Fn(&K) -> K,
+    key: impl Fn(&str) -> String,
+) -> EdgeMap<K> {
+    let mut grouped: EdgeMap<K> = HashMap::new();
+    for (target, pods) in map {
+        let edges = grouped.entry(name(target)).or_default();
+        for (client, stats) in pods {
+            match edges.entry(key(client)) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge(stats),
+                Entry::Vacant(entry) => {
+                    entry.insert(stats.clone());
+                }
+            }
+        }
+    }
+    grouped
+}
+
+/// Name -> querying pod -> stats.
+pub type EdgeMap<K = String> = HashMap<K, HashMap<String, EdgeStats>>;
 
 #[derive(Serialize, Default, Debug, Clone)]
 pub struct DnsData {
-    pub(crate) internal: HashMap<String, Vec<String>>,
-    pub(crate) external: HashMap<String, Vec<String>>,
+    #[serde(serialize_with = "serialize_internal")]
+    pub(crate) internal: EdgeMap<InternalName>,
+    pub(crate) external: EdgeMap,
+    /// PTR lookups, keyed by the address asked about.
+    pub(crate) reverse: EdgeMap<ReverseName>,
+    /// Search-path cost per querying pod.
+    pub(crate) search_path: HashMap<String, SearchPathCost>,
+    /// Internal names without a Service or namespace behind them.
+    pub(crate) dangling: HashMap<String, DanglingDependency>,
+    /// Querying clients that are nodes rather than pods.
+    pub(crate) nodes: BTreeSet<String>,
+    /// Whether the input carries answer records (dnstap). Log lines do not,
+    /// and empty answers cannot be told apart without them.
+    pub(crate) answers_known: bool,
+    /// Workload and namespace of every querying pod, to group them.
+    #[serde(skip)]
+    pub(crate) clients: HashMap<String, ClientKeys>,
+}
+
+/// Internal edges with the pod their name resolved to in a `target` field,
+/// for the web UI to draw them from pod to pod.
+fn serialize_internal<S: Serializer>(
+    internal: &EdgeMap<InternalName>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Edge<'a> {
+        #[serde(flatten)]
+        stats: &'a EdgeStats,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+    }
+
+    serializer.collect_map(internal.iter().map(|(name, pods)| {
+        let edges: HashMap<&String, Edge> = pods
+            .iter()
+            .map(|(pod, stats)| {
+                let target = name.target_pod();
+                (pod, Edge { stats, target })
+            })
+            .collect();
+        (name, edges)
+    }))
+}
+
+impl DnsData {
+    /// The data with the querying pods grouped at the granularity. Clients
+    /// recorded at a coarser one stay as they are.
+    pub fn aggregate(&self, granularity: Granularity) -> DnsData {
+        if granularity == Granularity::Pod {
+            return self.clone();
+        }
+        let key = |client: &str| {
+            self.clients
+                .get(client)
+                .map_or(client, |keys| keys.get(granularity))
+                .to_string()
+        };
+        DnsData {
+            internal: regroup_edges(&self.internal, |name| name.map_pod(key), key),
+            external: regroup_edges(&self.external, String::clone, key),
+            reverse: regroup_edges(&self.reverse, |name| name.map_pod(key), key),
+            search_path: regroup(&self.search_path, key, SearchPathCost::merge),
+            dangling: self
+                .dangling
+                .iter()
+                .map(|(name, dependency)| {
+                    let pods = regroup(&dependency.pods, key, Sighting::merge);
+                    (
+                        name.clone(),
+                        DanglingDependency {
+                            pods,
+                            ..dependency.clone()
+                        },
+                    )
+                })
+                .collect(),
+            nodes: self.nodes.clone(),
+            answers_known: self.answers_known,
+            clients: self
+                .clients
+                .iter()
+                .map(|(client, keys)| (key(client), keys.clone().coarsen(granularity)))
+                .collect(),
+        }
+    }
+
+    /// Every name with its querying pods, whatever kind of name it is.
+    fn edges(&self) -> impl Iterator<Item = (String, &HashMap<String, EdgeStats>)> {
+        let internal = self
+            .internal
+            .iter()
+            .map(|(name, pods)| (name.to_string(), pods));
+        let external = self
+            .external
+            .iter()
+            .map(|(name, pods)| (name.clone(), pods));
+        let reverse = self
+            .reverse
+            .iter()
+            .map(|(name, pods)| (name.to_string(), pods));
+        internal.chain(external).chain(reverse)
+    }
+
+    /// Pods with failed queries, highest failure rate first.
+    pub fn errors(&self) -> Vec<ClientErrors> {
+        let mut clients: HashMap<&str, ClientErrors> = HashMap::new();
+        for (name, pods) in self.edges() {
+            for (pod, stats) in pods {
+                let client = clients.entry(pod).or_insert_with(|| ClientErrors {
+                    client: pod.clone(),
+                    ..Default::default()
+                });
+                client.queries += stats.queries;
+                let failures = stats.failures();
+                if failures == 0 {
+                    continue;
+                }
+                client.failures += failures;
+                for (rcode, count) in stats.rcodes.iter().filter(|(rcode, _)| *rcode != "NOERROR") {
+                    *client.rcodes.entry(rcode.clone()).or_default() += count;
+                }
+                client.names.push((name.clone(), failures));
+            }
+        }
+
+        let mut errors: Vec<ClientErrors> = clients
+            .into_values()
+            .filter(|client| client.failures > 0)
+            .map(|mut client| {
+                client.failure_rate = client.failures as f64 / client.queries as f64;
+                client
+                    .names
+                    .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                client.names.truncate(MAX_FAILING_NAMES);
+                client
+            })
+            .collect();
+        errors.sort_by(|a, b| {
+            b.failure_rate
+                .total_cmp(&a.failure_rate)
+                .then_with(|| b.failures.cmp(&a.failures))
+        });
+        errors
+    }
+
+    /// Pods sending AAAA queries that always come back empty, most wasted
+    /// queries first. Needs the answers, so only dnstap input shows up here.
+    pub fn aaaa_waste(&self) -> AaaaWasteReport {
+        let clients = self.aaaa_waste_clients();
+        let empty_reason = if !self.answers_known {
+            Some(AaaaWasteEmpty::NoAnswers)
+        } else if !clients.is_empty() {
+            None
+        } else if self
+            .internal
+            .values()
+            .chain(self.external.values())
+            .chain(self.reverse.values())
+            .flat_map(HashMap::values)
+            .any(|stats| stats.qtypes.contains_key("AAAA"))
+        {
+            Some(AaaaWasteEmpty::AllAnswered)
+        } else {
+            Some(AaaaWasteEmpty::NoAaaaQueries)
+        };
+        AaaaWasteReport {
+            available: self.answers_known,
+            empty_reason,
+            clients,
+        }
+    }
+
+    fn aaaa_waste_clients(&self) -> Vec<AaaaWaste> {
+        let mut clients: HashMap<&str, AaaaWaste> = HashMap::new();
+        for (name, pods) in self.edges() {
+            for (pod, stats) in pods {
+                let aaaa = stats.qtypes.get("AAAA").copied().unwrap_or_default();
+                let empty = stats.empty.get("AAAA").copied().unwrap_or_default();
+                let client = clients.entry(pod).or_insert_with(|| AaaaWaste {
+                    client: pod.clone(),
+                    ..Default::default()
+                });
+                client.a_queries += stats.qtypes.get("A").copied().unwrap_or_default();
+                client.aaaa_queries += aaaa;
+                if aaaa > 0 && empty == aaaa {
+                    client.wasted_queries += aaaa;
+                    client.names.push((name.clone(), aaaa));
+                }
+            }
+        }
+
+        let mut waste: Vec<AaaaWaste> = clients
+            .into_values()
+            .filter(|client| client.wasted_queries > 0)
+            .map(|mut client| {
+                client
+                    .names
+                    .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                client.names.truncate(MAX_FAILING_NAMES);
+                client
+            })
+            .collect();
+        waste.sort_by_key(|client| std::cmp::Reverse(client.wasted_queries));
+        waste
+    }
+}
+
+/// AAAA waste of every client, see `DnsData::aaaa_waste`.
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct AaaaWasteReport {
+    /// `false` for log input, which does not carry the answers.
+    pub(crate) available: bool,
+    /// Why there are no clients, when there are none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) empty_reason: Option<AaaaWasteEmpty>,
+    pub(crate) clients: Vec<AaaaWaste>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AaaaWasteEmpty {
+    /// The input does not carry the answers, as CoreDNS logs do not.
+    NoAnswers,
+    /// No AAAA queries were seen.
+    NoAaaaQueries,
+    /// Every name queried for AAAA returned an AAAA record at least once.
+    AllAnswered,
+}
+
+/// AAAA queries of one client that never got an IPv6 answer.
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct AaaaWaste {
+    /// The pod, workload or namespace, depending on the granularity.
+    pub(crate) client: String,
+    pub(crate) a_queries: u64,
+    pub(crate) aaaa_queries: u64,
+    /// AAAA queries for names that never returned an AAAA record.
+    pub(crate) wasted_queries: u64,
+    /// Names with the most wasted AAAA queries, worst first.
+    pub(crate) names: Vec<(String, u64)>,
+}
+
+/// Failed queries of one client, for the errors view.
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct ClientErrors {
+    /// The pod, workload or namespace, depending on the granularity.
+    pub(crate) client: String,
+    pub(crate) queries: u64,
+    pub(crate) failures: u64,
+    pub(crate) failure_rate: f64,
+    /// Failures by response code.
+    pub(crate) rcodes: BTreeMap<String, u64>,
+    /// Names with the most failures, worst first.
+    pub(crate) names: Vec<(String, u64)>,
 }
 
 #[derive(Clone)]
 pub struct LogAnalyzer {
-    client: Client,
-    sender: Sender<String>,
-    receiver: Receiver<String>,
+    client: Option<Client>,
+    catalog: Option<ServiceCatalog>,
+    pods: Option<PodIndex>,
+    format: LogFormat,
+    classifier: Arc<DomainClassifier>,
+    search_path: Arc<Mutex<SearchPathTracker>>,
+    data: Arc<RwLock<DnsData>>,
+    sender: Sender<DnsData>,
+    receiver: Receiver<DnsData>,
 }
 
 impl LogAnalyzer {
     pub async fn new() -> Result<Self> {
-        let (sender, receiver) = watch::channel(String::from("asd"));
+        let (sender, receiver) = watch::channel(DnsData::default());
+        // Offline input only touches the kubeconfig when asked to.
+        let client = if !CONFIG.looks_up_cluster() && !CONFIG.discover_zones {
+            None
+        } else {
+            match Client::try_default().await {
+                Ok(client) => Some(client),
+                Err(err) if !CONFIG.follows_cluster() => {
+                    log::warn!("No cluster available, pods will not be resolved: {}", err);
+                    None
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+
+        suffixes::load(CONFIG.tld_file.as_deref(), CONFIG.psl_file.as_deref())?;
+
+        let mut zones = CONFIG.internal_zones.clone();
+        if CONFIG.discover_zones {
+            match &client {
+                Some(client) => {
+                    let discovered = domain::discover_zones(client).await?;
+                    log::info!("Discovered cluster zones: {}", discovered.join(", "));
+                    zones.extend(discovered);
+                }
+                None => log::warn!("Cannot discover cluster zones without a cluster"),
+            }
+        }
+
+        let classifier = DomainClassifier::new(zones, CONFIG.external_aggregation);
+        let search_path = SearchPathTracker::new(classifier.internal_zones().to_vec());
+        let lookups = client.as_ref().filter(|_| CONFIG.looks_up_cluster());
         Ok(Self {
-            client: Client::try_default().await?,
+            catalog: lookups.map(ServiceCatalog::start),
+            pods: lookups.map(PodIndex::start),
+            client,
+            format: LogFormat::compile(&CONFIG.log_format)?,
+            classifier: Arc::new(classifier),
+            search_path: Arc::new(Mutex::new(search_path)),
+            data: Default::default(),
             sender,
             receiver,
         })
     }
 
-    pub async fn get_update(&mut self) -> Result<String> {
+    pub async fn get_update(&mut self) -> Result<DnsData> {
         self.receiver.changed().await?;
         Ok(self.receiver.borrow().clone())
     }
 
-    fn extract_domain_name(query_name: &str, response_code: &str) -> Option<(String, bool)> {
-        let query = query_name.trim_end_matches('.');
+    /// The latest published snapshot.
+    pub fn latest(&self) -> DnsData {
+        self.receiver.borrow().clone()
+    }
 
-        if query.ends_with(".svc.cluster.local") && response_code == "NOERROR" {
-            Some((
-                query.split('.').next().unwrap_or("unknown").to_string(),
-                true,
-            ))
-        } else if TLDS
-            .iter()
-            .any(|tld| query.to_lowercase().ends_with(&format!(".{}", tld)))
-            && response_code == "NOERROR"
-        {
-            Some((query.to_string(), false))
-        } else {
-            None
+    /// Returns the latest snapshot if it changed since the last call, without waiting.
+    pub fn try_update(&mut self) -> Option<DnsData> {
+        match self.receiver.has_changed() {
+            Ok(true) => Some(self.receiver.borrow_and_update().clone()),
+            _ => None,
         }
     }
 
     pub async fn analyze_loop(&self) -> Result<()> {
-        let pods: Api<Pod> = Api::namespaced(self.client.clone(), &CONFIG.coredns_ns);
-        let coredns_pod = pods
-            .list(&kube::api::ListParams::default().labels(&CONFIG.coredns_label_selector))
-            .await?
-            .items
-            .into_iter()
-            .next()
-            .ok_or_else(|| anyhow::anyhow!("CoreDNS pod not found"))?;
-
-        // Unwrap ok need pod to continue
-        let pod_name = coredns_pod.metadata.name.unwrap();
-        log::info!("Found CoreDNS pod: {}", pod_name);
+        let analyzer = self.clone();
+        if let Some(input) = &CONFIG.input {
+            let lines = input::read_lines(input)
+                .map_err(|err| anyhow::anyhow!("Failed to read {}: {}", input, err))?;
+            tokio::spawn(async move {
+                analyzer.wait_for_pods().await;
+                analyzer.ingest_input(input, lines).await
+            });
+        } else if let Some(addr) = &CONFIG.dnstap_listen {
+            log::info!("Listening for dnstap on {}", addr);
+            let taps = dnstap::listen(addr).await?;
+            tokio::spawn(async move {
+                analyzer.wait_for_pods().await;
+                analyzer.ingest_taps(taps, None).await
+            });
+        } else if let Some(path) = &CONFIG.dnstap_file {
+            let taps = dnstap::read_file(path).await?;
+            tokio::spawn(async move {
+                analyzer.wait_for_pods().await;
+                analyzer.ingest_taps(taps, CONFIG.replay_speed).await
+            });
+        } else if let Some(client) = &self.client {
+            let pods: Api<Pod> = Api::namespaced(client.clone(), &CONFIG.coredns_ns);
+            tokio::spawn(async move {
+                analyzer.wait_for_pods().await;
+                analyzer.watch_replicas(pods).await
+            });
+        } else {
+            anyhow::bail!("A cluster is required to follow CoreDNS logs");
+        }
 
-        let lp = LogParams {
-            container: Some("coredns".to_string()),
-            follow: true,
-            tail_lines: Some(1),
-            ..Default::default()
-        };
+        let analyzer = self.clone();
+        tokio::spawn(async move {
+            // Live queries carry the current time, release the ones whose
+            // lookup is complete even when no query follows them.
+            let live = CONFIG.follows_cluster() || CONFIG.dnstap_listen.is_some();
+            loop {
+                if live {
+                    let held = analyzer.search_path.lock().await.advance(Utc::now());
+                    analyzer.record_held(held).await;
+                }
+                let _ = analyzer.sender.send(analyzer.data.read().await.clone());
+                sleep(Duration::from_secs(2)).await;
+            }
+        });
 
-        let mut logs = pods.log_stream(&pod_name, &lp).await?.lines();
-        let re = Regex::new(
-            r#"\[INFO\] ([\d.:]+) - \d+ "([\w]+) IN ([\w.-]+) udp \d+ [\w]+ \d+" (\w+)"#,
-        )?;
+        Ok(())
+    }
 
-        let internal_map: Arc<RwLock<HashMap<String, Vec<String>>>> =
-            Arc::new(RwLock::new(HashMap::new()));
-        let external_map: Arc<RwLock<HashMap<String, Vec<String>>>> =
-            Arc::new(RwLock::new(HashMap::new()));
+    /// Clients are kept by address until the pods are listed. Gives the
+    /// listing a head start over the first queries, without ever blocking
+    /// ingestion on it.
+    async fn wait_for_pods(&self) {
+        if let Some(pods) = &self.pods
+            && timeout(PODS_READY_TIMEOUT, pods.ready()).await.is_err()
+        {
+            log::warn!(
+                "Pods not listed after {:?}, clients are kept by address until they are",
+                PODS_READY_TIMEOUT
+            );
+        }
+    }
 
-        let internal_clone = internal_map.clone();
-        let external_clone = external_map.clone();
-        let client = self.client.clone();
-        let sender = self.sender.clone();
+    /// Follows the CoreDNS pods matching the label selector, attaching a log
+    /// stream to every running replica and dropping the streams of deleted ones.
+    async fn watch_replicas(&self, pods: Api<Pod>) {
+        let mut streams: HashMap<String, AbortHandle> = HashMap::new();
+        let mut listed: HashSet<String> = HashSet::new();
+        let config = watcher::Config::default().labels(&CONFIG.coredns_label_selector);
+        let mut events = watcher(pods.clone(), config).default_backoff().boxed();
 
-        tokio::spawn(async move {
-            loop {
-                let log = logs.try_next().await;
-                let line = match log {
-                    Ok(log) => match log {
-                        Some(line) => line,
-                        None => continue,
-                    },
-                    Err(err) => {
-                        log::error!("{}", err);
-                        continue;
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(Event::Init) => listed.clear(),
+                Ok(Event::InitApply(pod)) => {
+                    listed.extend(self.attach_replica(&mut streams, &pods, pod));
+                }
+                Ok(Event::InitDone) => streams.retain(|replica, stream| {
+                    if listed.contains(replica) {
+                        return true;
                     }
-                };
-
-                if let Some(captures) = re.captures(&line) {
-                    let (client_ip, _query_type, query_name, response_code) =
-                        match parse_infos(captures) {
-                            Some(res) => res,
-                            None => continue,
-                        };
-
-                    if let Some((domain_name, is_internal)) =
-                        Self::extract_domain_name(query_name, response_code)
+                    log::info!("CoreDNS pod {} is gone, closing its log stream", replica);
+                    stream.abort();
+                    false
+                }),
+                Ok(Event::Apply(pod)) => {
+                    self.attach_replica(&mut streams, &pods, pod);
+                }
+                Ok(Event::Delete(pod)) => {
+                    if let Some(replica) = pod.metadata.name
+                        && let Some(stream) = streams.remove(&replica)
                     {
-                        let pod_name = resolve_pod(&client, client_ip).await;
-                        let map_to_update = if is_internal {
-                            &internal_clone
-                        } else {
-                            &external_clone
-                        };
-
-                        map_to_update
-                            .write()
-                            .await
-                            .entry(domain_name)
-                            .or_insert_with(Vec::new)
-                            .push(pod_name);
+                        log::info!("CoreDNS pod {} deleted, closing its log stream", replica);
+                        stream.abort();
                     }
                 }
+                Err(err) => log::error!("CoreDNS pod watch failed: {}", err),
             }
-        });
+        }
+    }
 
-        tokio::spawn(async move {
-            loop {
-                let dns_data = DnsData {
-                    internal: (*internal_map.read().await).clone(),
-                    external: (*external_map.read().await).clone(),
-                };
-                match serde_json::to_string(&dns_data) {
-                    Ok(msg) => _ = sender.send(msg),
-                    Err(err) => log::error!("Error: {}", err),
-                };
+    /// Opens a log stream for the pod if it is running and not followed yet.
+    /// Returns the pod name so that the caller can keep track of live replicas.
+    fn attach_replica(
+        &self,
+        streams: &mut HashMap<String, AbortHandle>,
+        pods: &Api<Pod>,
+        pod: Pod,
+    ) -> Option<String> {
+        let replica = pod.metadata.name?;
+        let running = pod
+            .status
+            .and_then(|status| status.phase)
+            .is_some_and(|phase| phase == "Running");
 
-                sleep(Duration::from_secs(2)).await;
+        if running && !streams.contains_key(&replica) {
+            log::info!("Found CoreDNS pod: {}", replica);
+            let analyzer = self.clone();
+            let pods = pods.clone();
+            let name = replica.clone();
+            let task = tokio::spawn(async move { analyzer.follow_replica(pods, name).await });
+            streams.insert(replica.clone(), task.abort_handle());
+        }
+
+        Some(replica)
+    }
+
+    /// Keeps a log stream open for the replica, reconnecting with an
+    /// exponential backoff whenever it fails or ends.
+    async fn follow_replica(self, pods: Api<Pod>, replica: String) {
+        let replica: Arc<str> = Arc::from(replica);
+        let mut backoff = MIN_BACKOFF;
+        let mut cursor = LogCursor::default();
+        loop {
+            let started = Instant::now();
+            match self.stream_replica(&pods, &replica, &mut cursor).await {
+                Ok(()) => log::warn!("Log stream of {} ended", replica),
+                Err(err) => log::error!("Log stream of {} failed: {}", replica, err),
             }
-        });
+
+            if started.elapsed() > MAX_BACKOFF {
+                backoff = MIN_BACKOFF;
+            }
+            log::info!("Reconnecting to {} in {:?}", replica, backoff);
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    async fn stream_replica(
+        &self,
+        pods: &Api<Pod>,
+        replica: &Arc<str>,
+        cursor: &mut LogCursor,
+    ) -> Result<()> {
+        let mut logs = pods
+            .log_stream(replica, &cursor.log_params())
+            .await?
+            .lines();
+        while let Some(line) = logs.try_next().await? {
+            if let Some((timestamp, line)) = cursor.advance(&line) {
+                self.process_line(replica, timestamp, line).await;
+            }
+        }
 
         Ok(())
     }
+
+    /// Feeds an exported log file, or stdin for `-`, through the same
+    /// pipeline as the live log streams.
+    async fn ingest_input(&self, input: &str, mut lines: mpsc::Receiver<std::io::Result<String>>) {
+        let source = if input == "-" { "stdin" } else { input };
+        let mut clock = ReplayClock::new(CONFIG.replay_speed);
+        // Replicas named by the `kubectl logs --prefix` of the lines.
+        let mut replicas: HashSet<Arc<str>> = HashSet::new();
+
+        while let Some(line) = lines.recv().await {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    log::error!("Failed to read {}: {}", source, err);
+                    break;
+                }
+            };
+            let (replica, line) = input::split_prefix(&line).unwrap_or((source, &line));
+            let replica = match replicas.get(replica) {
+                Some(replica) => replica.clone(),
+                None => {
+                    let replica: Arc<str> = Arc::from(replica);
+                    replicas.insert(replica.clone());
+                    replica
+                }
+            };
+            let (timestamp, line) = split_timestamp(line);
+            if let Some(timestamp) = timestamp {
+                clock.wait_until(timestamp).await;
+            }
+            self.process_line(&replica, timestamp, line).await;
+        }
+
+        self.flush_search_path().await;
+        log::info!("Finished reading {}", source);
+    }
+
+    /// Records the events decoded from dnstap frames, tagged with the
+    /// identity of the CoreDNS instance that sent them. Recorded frames are
+    /// replayed at `replay_speed`, live ones are recorded as they come.
+    async fn ingest_taps(&self, mut taps: mpsc::Receiver<Tap>, replay_speed: Option<f64>) {
+        let mut clock = ReplayClock::new(replay_speed);
+        while let Some(tap) = taps.recv().await {
+            if let Some(timestamp) = tap.event.timestamp {
+                clock.wait_until(timestamp).await;
+            }
+            self.record(&tap.identity, tap.event).await;
+        }
+        self.flush_search_path().await;
+        log::info!("dnstap input finished");
+    }
+
+    async fn process_line(&self, replica: &Arc<str>, timestamp: Option<DateTime<Utc>>, line: &str) {
+        if let Some(mut event) = self.format.parse(line) {
+            event.timestamp = timestamp;
+            self.record(replica, event).await;
+        }
+    }
+
+    /// Queries stay with the search-path tracker until it knows which
+    /// lookup they belong to.
+    async fn record(&self, replica: &Arc<str>, event: DnsQueryEvent) {
+        let released = self.search_path.lock().await.observe(replica, event);
+        self.record_held(released).await;
+    }
+
+    async fn record_held(&self, held: Vec<Held>) {
+        for held in held {
+            self.record_query(&held.replica, &held.event, held.lookup.as_ref())
+                .await;
+        }
+    }
+
+    /// Records the queries the search-path tracker still holds, once the
+    /// input is exhausted.
+    async fn flush_search_path(&self) {
+        let held = self.search_path.lock().await.flush();
+        self.record_held(held).await;
+    }
+
+    async fn record_query(&self, replica: &str, event: &DnsQueryEvent, lookup: Option<&Lookup>) {
+        let mut domain_name = self.classifier.extract_domain_name(event);
+        if let Some(name) = self.missing_service(event, lookup) {
+            domain_name = Some(DomainName::Internal(name));
+        }
+        if lookup.is_none() && domain_name.is_none() {
+            return;
+        }
+
+        let (client, target) = match &self.pods {
+            Some(pods) => {
+                let target = match &mut domain_name {
+                    Some(DomainName::Internal(name)) => resolve_target(pods, name, event.timestamp),
+                    Some(DomainName::Reverse(name)) => {
+                        resolve_owner(self.catalog.as_ref(), pods, name, event.timestamp)
+                    }
+                    _ => None,
+                };
+                (pods.client(event.client_ip, event.timestamp), target)
+            }
+            None => (None, None),
+        };
+        let client = client
+            .unwrap_or_else(|| {
+                let label = cidr::label(&CONFIG.cidr_labels, event.client_ip);
+                ClientKeys::address(event.client_ip, label)
+            })
+            .coarsen(CONFIG.granularity);
+        let pod_name = client.pod.clone();
+        // Names answered by other plugins (`fallthrough`, multicluster
+        // zones) resolve without a Service, only failures can dangle.
+        let dangling = match (&self.catalog, &domain_name) {
+            (Some(catalog), Some(DomainName::Internal(name))) if event.rcode == "NXDOMAIN" => {
+                catalog.check(name).map(|reason| (name.to_string(), reason))
+            }
+            _ => None,
+        };
+
+        let mut data = self.data.write().await;
+        data.answers_known |= event.answers.is_some();
+        if client.node {
+            data.nodes.insert(pod_name.clone());
+        }
+        data.clients.entry(pod_name.clone()).or_insert(client);
+        if let Some(target) = target {
+            data.clients.entry(target.pod.clone()).or_insert(target);
+        }
+        if let Some((name, reason)) = dangling {
+            let dependency = data
+                .dangling
+                .entry(name)
+                .or_insert_with(|| DanglingDependency::new(reason));
+            dependency.reason = reason;
+            dependency.observe(&pod_name, event.timestamp.unwrap_or_else(Utc::now));
+        }
+        if let Some(lookup) = lookup {
+            data.search_path
+                .entry(pod_name.clone())
+                .or_default()
+                .observe(lookup);
+        }
+
+        if let Some(domain_name) = domain_name {
+            let pods = match domain_name {
+                DomainName::Internal(name) => data.internal.entry(name).or_default(),
+                DomainName::External(name) => data.external.entry(name).or_default(),
+                DomainName::Reverse(name) => data.reverse.entry(name).or_default(),
+            };
+            let stats = pods.entry(pod_name).or_default();
+            stats.observe(replica, event);
+            stats.expansions += lookup.map_or(0, |lookup| lookup.expansions);
+        }
+    }
+
+    /// The Service a lookup that failed through the whole search list was
+    /// meant for, when it is missing from a namespace that exists: the
+    /// record of the closest search domain, `web.shop.svc.cluster.local`
+    /// for `web.shop`. A missing namespace is as likely a name outside the
+    /// cluster, the lookup stays external then.
+    fn missing_service(
+        &self,
+        event: &DnsQueryEvent,
+        lookup: Option<&Lookup>,
+    ) -> Option<InternalName> {
+        let catalog = self.catalog.as_ref()?;
+        if event.rcode != "NXDOMAIN" {
+            return None;
+        }
+        let (_, name) = lookup?
+            .failed_internal
+            .iter()
+            .filter_map(|name| Some((name.len(), self.classifier.internal_name(name)?)))
+            .min_by_key(|(len, _)| *len)?;
+        (catalog.check(&name) == Some(DanglingReason::MissingService)).then_some(name)
+    }
+}
+
+/// Position in a replica's log stream, used to resume after a reconnect
+/// without losing or double counting lines.
+#[derive(Default)]
+struct LogCursor {
+    last_seen: Option<DateTime<Utc>>,
+    /// Lines already processed that carry the `last_seen` timestamp.
+    seen_at_last: HashSet<String>,
+}
+
+impl LogCursor {
+    fn log_params(&self) -> LogParams {
+        LogParams {
+            container: Some("coredns".to_string()),
+            follow: true,
+            timestamps: true,
+            // The API only takes whole seconds, lines between the truncated
+            // `since_time` and `last_seen` are dropped by `advance`.
+            since_time: self.last_seen,
+            tail_lines: if self.last_seen.is_none() {
+                Some(1)
+            } else {
+                None
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Splits the timestamp prefix off the line, returning `None` if the
+    /// line was already processed before the stream was reopened.
+    fn advance<'a>(&mut self, line: &'a str) -> Option<(Option<DateTime<Utc>>, &'a str)> {
+        let (timestamp, line) = split_timestamp(line);
+        let Some(timestamp) = timestamp else {
+            return Some((None, line));
+        };
+
+        match self.last_seen {
+            Some(last) if timestamp < last => return None,
+            Some(last) if timestamp == last => {
+                if !self.seen_at_last.insert(line.to_string()) {
+                    return None;
+                }
+            }
+            _ => {
+                self.last_seen = Some(timestamp);
+                self.seen_at_last.clear();
+                self.seen_at_last.insert(line.to_string());
+            }
+        }
+
+        Some((Some(timestamp), line))
+    }
 }
 
-fn parse_infos(captures: Captures<'_>) -> Option<(&str, &str, &str, &str)> {
-    return Some((
-        captures.get(1)?.as_str(),
-        captures.get(2)?.as_str(),
-        captures.get(3)?.as_str(),
-        captures.get(4)?.as_str(),
-    ));
+/// Splits the RFC 3339 prefix added by `timestamps=true` (or
+/// `kubectl logs --timestamps`) off a log line.
+fn split_timestamp(line: &str) -> (Option<DateTime<Utc>>, &str) {
+    if let Some((prefix, rest)) = line.split_once(' ')
+        && let Ok(timestamp) = DateTime::parse_from_rfc3339(prefix)
+    {
+        return (Some(timestamp.with_timezone(&Utc)), rest);
+    }
+    (None, line)
 }
 
-async fn resolve_pod(client: &Client, ip: &str) -> String {
-    let pods: Api<Pod> = Api::all(client.clone());
-    let lp = ListParams::default().fields(&format!(
-        "status.podIP={}",
-        ip.split(":").collect::<Vec<_>>()[0]
-    ));
+/// Fills in the pod behind pod-IP and headless member records, as they were
+/// at the time of the query, and returns its keys. Like clients, the pod is
+/// recorded at the configured granularity.
+fn resolve_target(
+    pods: &PodIndex,
+    name: &mut InternalName,
+    at: Option<DateTime<Utc>>,
+) -> Option<ClientKeys> {
+    let (target, keys) = match name {
+        InternalName::Pod { ip, pod, .. } => (pod, pods.target(*ip, at)),
+        InternalName::Endpoint {
+            hostname,
+            service,
+            pod,
+        } => {
+            let keys = match domain::parse_dashed_ip(hostname) {
+                Some(ip) => pods.target(ip, at),
+                None => pods.member(hostname, service),
+            };
+            (pod, keys)
+        }
+        InternalName::Service(_) | InternalName::Srv(_) | InternalName::Other(_) => return None,
+    };
+    let keys = keys?.coarsen(CONFIG.granularity);
+    *target = Some(keys.pod.clone());
+    Some(keys)
+}
+
+/// Fills in the Service, pod or node owning the address of a reverse
+/// lookup, and returns the keys of the pod when it is one.
+fn resolve_owner(
+    catalog: Option<&ServiceCatalog>,
+    pods: &PodIndex,
+    name: &mut ReverseName,
+    at: Option<DateTime<Utc>>,
+) -> Option<ClientKeys> {
+    if let Some(service) = catalog.and_then(|catalog| catalog.ip_owner(name.ip)) {
+        name.owner = Some(service);
+        return None;
+    }
+    let Some(keys) = pods.target(name.ip, at) else {
+        name.owner = pods.node(name.ip).map(IpOwner::Node);
+        return None;
+    };
+    let keys = keys.coarsen(CONFIG.granularity);
+    name.owner = Some(IpOwner::Pod(keys.pod.clone()));
+    Some(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ServiceId;
+    use k8s_openapi::api::core::v1::{Namespace, Service};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use k8s_openapi::chrono::TimeDelta;
+
+    #[test]
+    fn splits_timestamps() {
+        let (timestamp, line) = split_timestamp("2025-01-01T10:00:00.5Z [INFO] query");
+        assert_eq!(
+            timestamp,
+            Some("2025-01-01T10:00:00.5Z".parse::<DateTime<Utc>>().unwrap())
+        );
+        assert_eq!(line, "[INFO] query");
+        assert_eq!(split_timestamp("[INFO] query"), (None, "[INFO] query"));
+    }
+
+    #[test]
+    fn cursor_skips_lines_replayed_after_a_reconnect() {
+        let mut cursor = LogCursor::default();
+        assert!(cursor.advance("2025-01-01T10:00:00Z a").is_some());
+        assert!(cursor.advance("2025-01-01T10:00:01Z b").is_some());
+        assert!(cursor.advance("2025-01-01T10:00:01Z c").is_some());
 
-    match pods.list(&lp).await {
-        Ok(pod_list) => pod_list
-            .items
-            .into_iter()
-            .next()
-            .and_then(|pod| pod.metadata.name)
-            .unwrap_or_else(|| "unknown".to_string()),
-        Err(_) => "unknown".to_string(),
+        // The stream is reopened at the truncated `since_time`
+        assert!(cursor.advance("2025-01-01T10:00:00Z a").is_none());
+        assert!(cursor.advance("2025-01-01T10:00:01Z b").is_none());
+        assert!(cursor.advance("2025-01-01T10:00:01Z c").is_none());
+        // Lines sharing the last timestamp that were not seen yet are kept
+        assert!(cursor.advance("2025-01-01T10:00:01Z d").is_some());
+        assert!(cursor.advance("2025-01-01T10:00:02Z b").is_some());
+        assert!(cursor.advance("2025-01-01T10:00:01Z d").is_none());
+    }
+
+    #[test]
+    fn cursor_keeps_lines_without_timestamps() {
+        let mut cursor = LogCursor::default();
+        assert_eq!(cursor.advance("a"), Some((None, "a")));
+        assert_eq!(cursor.advance("a"), Some((None, "a")));
+        assert!(cursor.log_params().tail_lines.is_some());
+    }
+
+    fn analyzer(catalog: ServiceCatalog) -> LogAnalyzer {
+        let classifier = DomainClassifier::new(
+            ["cluster.local".to_string()],
+            crate::config::ExternalAggregation::Fqdn,
+        );
+        let (sender, receiver) = watch::channel(DnsData::default());
+        LogAnalyzer {
+            client: None,
+            catalog: Some(catalog),
+            pods: None,
+            format: LogFormat::compile("{remote} {type} {name} {rcode}").unwrap(),
+            search_path: Arc::new(Mutex::new(SearchPathTracker::new(
+                classifier.internal_zones().to_vec(),
+            ))),
+            classifier: Arc::new(classifier),
+            data: Default::default(),
+            sender,
+            receiver,
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_missing_services_behind_failed_search_paths() {
+        let meta = |name: &str, namespace: Option<&str>| ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: namespace.map(str::to_string),
+            ..Default::default()
+        };
+        let catalog = ServiceCatalog::listed(
+            vec![Service {
+                metadata: meta("web", Some("shop")),
+                ..Default::default()
+            }],
+            vec![Namespace {
+                metadata: meta("shop", None),
+                ..Default::default()
+            }],
+        );
+        let analyzer = analyzer(catalog);
+        let replica: Arc<str> = Arc::from("coredns-1");
+        let start: DateTime<Utc> = "2025-01-01T10:00:00Z".parse().unwrap();
+        for (millis, name) in [
+            "web-api.shop.svc.cluster.local.",
+            "web-api.svc.cluster.local.",
+            "web-api.cluster.local.",
+            "web-api.",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let line = format!("[INFO] 10.244.0.5 A {} NXDOMAIN", name);
+            let at = start + TimeDelta::milliseconds(millis as i64);
+            analyzer.process_line(&replica, Some(at), &line).await;
+        }
+        analyzer.flush_search_path().await;
+
+        let data = analyzer.data.read().await;
+        let name = InternalName::Service(ServiceId::new("web-api", "shop"));
+        assert_eq!(
+            data.dangling["web-api.shop"].reason,
+            DanglingReason::MissingService
+        );
+        assert!(
+            data.dangling["web-api.shop"]
+                .pods
+                .contains_key("10.244.0.5")
+        );
+        assert_eq!(data.internal[&name]["10.244.0.5"].expansions, 3);
+        assert!(data.external.is_empty());
+    }
+
+    fn api_pod(name: &str) -> ClientKeys {
+        ClientKeys {
+            pod: format!("shop/{}", name),
+            workload: "shop/deployment/api".to_string(),
+            namespace: "shop".to_string(),
+            node: false,
+        }
+    }
+
+    fn edge(client: &str, queries: u64) -> HashMap<String, EdgeStats> {
+        HashMap::from([(
+            client.to_string(),
+            EdgeStats {
+                queries,
+                ..Default::default()
+            },
+        )])
+    }
+
+    #[test]
+    fn aggregates_the_pods_names_point_at() {
+        let ip = "10.244.0.5".parse().unwrap();
+        let target = |pod: &str| InternalName::Pod {
+            ip,
+            namespace: "shop".to_string(),
+            pod: Some(format!("shop/{}", pod)),
+        };
+        let owner = |pod: &str| ReverseName {
+            ip,
+            owner: Some(IpOwner::Pod(format!("shop/{}", pod))),
+        };
+        let mut data = DnsData::default();
+        for pod in ["api-1", "api-2", "api-3"] {
+            data.clients.insert(format!("shop/{}", pod), api_pod(pod));
+        }
+        // The address was reused by another replica of the same Deployment
+        data.internal.insert(target("api-1"), edge("shop/api-3", 2));
+        data.internal.insert(target("api-2"), edge("shop/api-3", 3));
+        data.reverse.insert(owner("api-1"), edge("shop/api-3", 1));
+        data.reverse.insert(owner("api-2"), edge("shop/api-3", 4));
+
+        let data = data.aggregate(Granularity::Workload);
+        let grouped = InternalName::Pod {
+            ip,
+            namespace: "shop".to_string(),
+            pod: Some("shop/deployment/api".to_string()),
+        };
+        assert_eq!(data.internal.len(), 1);
+        assert_eq!(data.internal[&grouped]["shop/deployment/api"].queries, 5);
+        let grouped = ReverseName {
+            ip,
+            owner: Some(IpOwner::Pod("shop/deployment/api".to_string())),
+        };
+        assert_eq!(data.reverse.len(), 1);
+        assert_eq!(data.reverse[&grouped]["shop/deployment/api"].queries, 5);
+    }
+
+    #[test]
+    fn serializes_the_pod_names_point_at() {
+        let mut data = DnsData::default();
+        let target = InternalName::Endpoint {
+            hostname: "web-0".to_string(),
+            service: ServiceId::new("web", "shop"),
+            pod: Some("shop/web-0".to_string()),
+        };
+        data.internal.insert(target, edge("shop/api-1", 1));
+        data.internal.insert(
+            InternalName::Service(ServiceId::new("web", "shop")),
+            edge("shop/api-1", 1),
+        );
+
+        let json = serde_json::to_value(&data).unwrap();
+        let edges = &json["internal"];
+        assert_eq!(
+            edges["web-0.web.shop (pod shop/web-0)"]["shop/api-1"]["target"],
+            "shop/web-0"
+        );
+        assert!(edges["web.shop"]["shop/api-1"].get("target").is_none());
+        assert_eq!(edges["web.shop"]["shop/api-1"]["queries"], 1);
+    }
+
+    #[test]
+    fn reports_errors_by_client() {
+        let mut data = DnsData::default();
+        for pod in ["api-1", "api-2"] {
+            data.clients.insert(format!("shop/{}", pod), api_pod(pod));
+            let mut stats = EdgeStats {
+                queries: 2,
+                ..Default::default()
+            };
+            stats.rcodes.insert("NXDOMAIN".to_string(), 1);
+            stats.rcodes.insert("NOERROR".to_string(), 1);
+            data.external.insert(
+                format!("{}.example.com.", pod),
+                HashMap::from([(format!("shop/{}", pod), stats)]),
+            );
+        }
+
+        let errors = data.aggregate(Granularity::Namespace).errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].client, "shop");
+        assert_eq!(errors[0].queries, 4);
+        assert_eq!(errors[0].failures, 2);
+    }
+
+    fn aaaa_edge(aaaa: u64, empty: u64) -> HashMap<String, EdgeStats> {
+        let mut stats = EdgeStats {
+            queries: aaaa * 2,
+            ..Default::default()
+        };
+        stats.qtypes.insert("A".to_string(), aaaa);
+        stats.qtypes.insert("AAAA".to_string(), aaaa);
+        if empty > 0 {
+            stats.empty.insert("AAAA".to_string(), empty);
+        }
+        HashMap::from([("shop/api-1".to_string(), stats)])
+    }
+
+    #[test]
+    fn reports_aaaa_queries_that_never_get_an_answer() {
+        let mut data = DnsData {
+            answers_known: true,
+            ..Default::default()
+        };
+        data.external
+            .insert("v4only.example.com".to_string(), aaaa_edge(2, 2));
+        data.external
+            .insert("dual.example.com".to_string(), aaaa_edge(3, 1));
+
+        let report = data.aaaa_waste();
+        assert_eq!(report.empty_reason, None);
+        assert_eq!(report.clients.len(), 1);
+        let client = &report.clients[0];
+        assert_eq!(client.client, "shop/api-1");
+        assert_eq!(client.aaaa_queries, 5);
+        assert_eq!(client.a_queries, 5);
+        assert_eq!(client.wasted_queries, 2);
+        assert_eq!(client.names, [("v4only.example.com".to_string(), 2)]);
+    }
+
+    #[test]
+    fn says_why_the_aaaa_report_is_empty() {
+        let mut data = DnsData::default();
+        data.external
+            .insert("v4only.example.com".to_string(), aaaa_edge(2, 2));
+        let report = data.aaaa_waste();
+        assert!(!report.available);
+        assert_eq!(report.empty_reason, Some(AaaaWasteEmpty::NoAnswers));
+
+        let mut data = DnsData {
+            answers_known: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            data.aaaa_waste().empty_reason,
+            Some(AaaaWasteEmpty::NoAaaaQueries)
+        );
+        data.external
+            .insert("dual.example.com".to_string(), aaaa_edge(3, 1));
+        assert_eq!(
+            data.aaaa_waste().empty_reason,
+            Some(AaaaWasteEmpty::AllAnswered)
+        );
     }
 }
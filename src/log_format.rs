@@ -0,0 +1,209 @@
+use anyhow::{Result, bail};
+use regex::Regex;
+
+use crate::dns_event::DnsQueryEvent;
+
+/// CoreDNS `{common}` format, also the default of the `log` plugin.
+pub const COMMON_LOG_FORMAT: &str = r#"{remote}:{port} - {>id} "{type} {class} {name} {proto} {size} {>do} {>bufsize}" {rcode} {>rflags} {rsize} {duration}"#;
+/// CoreDNS `{combined}` format.
+pub const COMBINED_LOG_FORMAT: &str = r#"{remote}:{port} - {>id} "{type} {class} {name} {proto} {size} {>do} {>bufsize}" {rcode} {>rflags} {rsize} {duration} "{>opcode}""#;
+
+/// IPv4, bare IPv6 or bracketed IPv6 as written by `{remote}` and `{local}`.
+const IP_PATTERN: &str = r"\[[\da-fA-F:.]+\]|[\da-fA-F:.]+";
+
+/// Placeholders an event cannot be built without.
+const REQUIRED: &[&str] = &["remote", "name", "rcode"];
+
+/// A CoreDNS `log` plugin format compiled into a regex with one named group
+/// per placeholder, see https://coredns.io/plugins/log/.
+#[derive(Debug, Clone)]
+pub struct LogFormat {
+    re: Regex,
+}
+
+impl LogFormat {
+    pub fn compile(template: &str) -> Result<Self> {
+        let template = template
+            .replace("{combined}", COMBINED_LOG_FORMAT)
+            .replace("{common}", COMMON_LOG_FORMAT);
+
+        let mut pattern = String::from(r"\[INFO\] ");
+        let mut groups = Vec::new();
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                bail!("Unterminated placeholder in log format: {}", template);
+            };
+            pattern.push_str(&regex::escape(&rest[..start]));
+
+            let placeholder = &rest[start + 1..start + len];
+            let (group, matcher) = placeholder_pattern(placeholder)?;
+            match group {
+                // The same placeholder can show up more than once, only
+                // the first occurrence is captured.
+                Some(group) if !groups.contains(&group) => {
+                    pattern.push_str(&format!("(?P<{}>{})", group, matcher));
+                    groups.push(group);
+                }
+                _ => pattern.push_str(&format!("(?:{})", matcher)),
+            }
+            rest = &rest[start + len + 1..];
+        }
+        pattern.push_str(&regex::escape(rest));
+
+        for required in REQUIRED {
+            if !groups.contains(required) {
+                bail!("Log format must contain the {{{}}} placeholder", required);
+            }
+        }
+
+        Ok(Self {
+            re: Regex::new(&pattern)?,
+        })
+    }
+
+    pub fn parse(&self, line: &str) -> Option<DnsQueryEvent> {
+        DnsQueryEvent::from_captures(&self.re.captures(line)?)
+    }
+}
+
+/// Returns the capture group name and the regex matching a placeholder.
+/// Placeholders that do not map to an event field are matched but not captured.
+fn placeholder_pattern(placeholder: &str) -> Result<(Option<&'static str>, &'static str)> {
+    Ok(match placeholder {
+        "remote" => (Some("remote"), IP_PATTERN),
+        "port" => (Some("port"), r"\d+"),
+        "local" => (None, IP_PATTERN),
+        ">id" => (Some("id"), r"\d+"),
+        "type" => (Some("type"), r"\w+"),
+        "class" => (Some("class"), r"\w+"),
+        "name" => (Some("name"), r"\S+"),
+        "proto" => (Some("proto"), r"\w+"),
+        "size" => (Some("size"), r"\d+"),
+        ">do" => (Some("do"), r"\w+"),
+        ">bufsize" => (Some("bufsize"), r"\d+"),
+        "rcode" => (Some("rcode"), r"\w+"),
+        ">rflags" => (Some("rflags"), r"[\w,]*"),
+        "rsize" => (Some("rsize"), r"\d+"),
+        "duration" => (Some("duration"), r"[\d.]+s"),
+        ">opcode" => (None, r"\w+"),
+        // Metadata (`{/plugin/key}`) and request headers (`{>header}`)
+        p if p.starts_with('/') || p.starts_with('>') => (None, r"\S*"),
+        p => bail!("Unknown placeholder in log format: {{{}}}", p),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    const COMMON_LINE: &str = r#"[INFO] 10.244.0.5:43211 - 4021 "AAAA IN api.stripe.com. udp 43 false 1232" NOERROR qr,rd,ra 129 0.000214s"#;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_common() {
+        let event = LogFormat::compile("{common}")
+            .unwrap()
+            .parse(COMMON_LINE)
+            .unwrap();
+        assert_eq!(event.client_ip, ip("10.244.0.5"));
+        assert_eq!(event.client_port, 43211);
+        assert_eq!(event.id, 4021);
+        assert_eq!(event.qtype, "AAAA");
+        assert_eq!(event.qclass, "IN");
+        assert_eq!(event.name, "api.stripe.com.");
+        assert_eq!(event.proto, "udp");
+        assert_eq!(event.size, 43);
+        assert!(!event.do_bit);
+        assert_eq!(event.bufsize, 1232);
+        assert_eq!(event.rcode, "NOERROR");
+        assert_eq!(event.rflags, ["qr", "rd", "ra"]);
+        assert_eq!(event.rsize, 129);
+        assert_eq!(event.duration.as_micros(), 214);
+    }
+
+    #[test]
+    fn parses_combined() {
+        let line = format!("{} \"0\"", COMMON_LINE);
+        let format = LogFormat::compile("{combined}").unwrap();
+        let event = format.parse(&line).unwrap();
+        assert_eq!(event.client_ip, ip("10.244.0.5"));
+        assert_eq!(event.rcode, "NOERROR");
+        assert!(format.parse(COMMON_LINE).is_none());
+    }
+
+    #[test]
+    fn parses_bracketed_ipv6_remote() {
+        let line = COMMON_LINE.replace("10.244.0.5", "[fd00:10:244::5]");
+        let event = LogFormat::compile("{common}")
+            .unwrap()
+            .parse(&line)
+            .unwrap();
+        assert_eq!(event.client_ip, ip("fd00:10:244::5"));
+        assert_eq!(event.client_port, 43211);
+    }
+
+    #[test]
+    fn maps_ipv4_mapped_remote_to_ipv4() {
+        let line = COMMON_LINE.replace("10.244.0.5", "[::ffff:10.244.0.5]");
+        let event = LogFormat::compile("{common}")
+            .unwrap()
+            .parse(&line)
+            .unwrap();
+        assert_eq!(event.client_ip, ip("10.244.0.5"));
+    }
+
+    #[test]
+    fn parses_empty_rflags() {
+        let line = COMMON_LINE.replace(" qr,rd,ra ", "  ");
+        let event = LogFormat::compile("{common}")
+            .unwrap()
+            .parse(&line)
+            .unwrap();
+        assert!(event.rflags.is_empty());
+        assert_eq!(event.rsize, 129);
+    }
+
+    #[test]
+    fn captures_repeated_placeholder_once() {
+        let line = format!("{} tc,aa", COMMON_LINE);
+        let event = LogFormat::compile("{common} {>rflags}")
+            .unwrap()
+            .parse(&line)
+            .unwrap();
+        assert_eq!(event.rflags, ["qr", "rd", "ra"]);
+    }
+
+    #[test]
+    fn fills_missing_fields() {
+        let event = LogFormat::compile("{remote} {name} {rcode}")
+            .unwrap()
+            .parse("[INFO] 10.244.0.5 example.com. NXDOMAIN")
+            .unwrap();
+        assert_eq!(event.qtype, "-");
+        assert_eq!(event.client_port, 0);
+        assert!(event.rflags.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_placeholder() {
+        let err = LogFormat::compile("{common} {bogus}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown placeholder in log format: {bogus}"
+        );
+    }
+
+    #[test]
+    fn rejects_missing_required_placeholder() {
+        let err = LogFormat::compile("{remote} {name}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Log format must contain the {rcode} placeholder"
+        );
+    }
+}
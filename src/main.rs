@@ -1,41 +1,59 @@
+mod catalog;
+mod cidr;
 mod config;
+mod dns_event;
+mod dnstap;
+mod domain;
+mod input;
 mod log_analyzer;
+mod log_format;
+mod pod_index;
+mod psl;
+mod search_path;
+mod suffix_set;
+mod suffixes;
 use anyhow::Result;
 use axum::{
+    Json, Router,
     extract::{
+        Query, State, WebSocketUpgrade,
         ws::{Message, WebSocket},
-        State, WebSocketUpgrade,
     },
     http::StatusCode,
     response::{Html, IntoResponse},
     routing::get,
-    Router,
 };
-use config::CONFIG;
+use config::{CONFIG, Granularity};
 use log_analyzer::LogAnalyzer;
-mod tlds;
+use serde::Deserialize;
 mod tui;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tui::test().await
-    // env_logger::init();
-    // let analyzer = LogAnalyzer::new().await?;
-    // let _ = analyzer.analyze_loop().await;
-    //
-    // let app = Router::new()
-    //     .route("/", get(root_get))
-    //     .route("/ws/v1/get_updates", get(get_updates))
-    //     .with_state(analyzer);
-    //
-    // log::info!(
-    //     "Starting Webserver on port: {}:{}",
-    //     CONFIG.server_addr, CONFIG.server_port
-    // );
-    // let listener =
-    //     tokio::net::TcpListener::bind(format!("{}:{}", CONFIG.server_addr, CONFIG.server_port))
-    //         .await?;
-    // axum::serve(listener, app).await?;
+    env_logger::init();
+    let analyzer = LogAnalyzer::new().await?;
+    analyzer.analyze_loop().await?;
+
+    if CONFIG.tui {
+        return tui::run(analyzer).await;
+    }
+
+    let app = Router::new()
+        .route("/", get(root_get))
+        .route("/ws/v1/get_updates", get(get_updates))
+        .route("/api/v1/errors", get(get_errors))
+        .route("/api/v1/aaaa_waste", get(get_aaaa_waste))
+        .with_state(analyzer);
+
+    log::info!(
+        "Starting Webserver on port: {}:{}",
+        CONFIG.server_addr,
+        CONFIG.server_port
+    );
+    let listener =
+        tokio::net::TcpListener::bind(format!("{}:{}", CONFIG.server_addr, CONFIG.server_port))
+            .await?;
+    axum::serve(listener, app).await?;
 
     Ok(())
 }
@@ -44,33 +62,61 @@ async fn root_get() -> impl IntoResponse {
     let markup = match tokio::fs::read_to_string("src/index.html").await {
         Ok(m) => m,
         Err(err) => {
-            log::error!("{}",err);
-            return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response()
+            log::error!("{}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response();
         }
     };
-    
+
     Html(markup).into_response()
 }
 
+/// `?granularity=pod|workload|namespace`, the recorded one by default.
+#[derive(Deserialize)]
+struct View {
+    granularity: Option<Granularity>,
+}
+
+impl View {
+    fn granularity(&self) -> Granularity {
+        self.granularity.unwrap_or(CONFIG.granularity)
+    }
+}
+
+async fn get_errors(
+    Query(view): Query<View>,
+    State(analyzer): State<LogAnalyzer>,
+) -> impl IntoResponse {
+    Json(analyzer.latest().aggregate(view.granularity()).errors())
+}
+
+async fn get_aaaa_waste(
+    Query(view): Query<View>,
+    State(analyzer): State<LogAnalyzer>,
+) -> impl IntoResponse {
+    Json(analyzer.latest().aggregate(view.granularity()).aaaa_waste())
+}
+
 async fn get_updates(
     ws: WebSocketUpgrade,
+    Query(view): Query<View>,
     State(analyzer): State<LogAnalyzer>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|ws: WebSocket| async {
-        match search_stream(analyzer, ws).await {
-            Ok(_) => (),
-            Err(err) => {
-                log::error!("{}", err);
-                return;
-            }
+    ws.on_upgrade(move |ws: WebSocket| async move {
+        if let Err(err) = search_stream(analyzer, ws, view.granularity()).await {
+            log::error!("{}", err);
         }
     })
 }
 
-async fn search_stream(mut analyzer: LogAnalyzer, mut ws: WebSocket) -> Result<()> {
+async fn search_stream(
+    mut analyzer: LogAnalyzer,
+    mut ws: WebSocket,
+    granularity: Granularity,
+) -> Result<()> {
     log::debug!("New websocket client connected!");
     loop {
-        let value = analyzer.get_update().await?;
+        let data = analyzer.get_update().await?.aggregate(granularity);
+        let value = serde_json::to_string(&data)?;
         ws.send(Message::Text(value.into())).await?;
         log::debug!("Sending update!");
     }
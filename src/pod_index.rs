@@ -0,0 +1,925 @@
+use futures::StreamExt;
+use k8s_openapi::api::apps::v1::ReplicaSet;
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{Node, Pod};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference};
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
+use kube::{
+    Api, Client,
+    core::PartialObjectMeta,
+    runtime::{
+        WatchStreamExt,
+        reflector::{ObjectRef, Store},
+        watcher::{self, Event, watcher},
+    },
+};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+use crate::catalog::reflect_metadata;
+use crate::cidr::CidrLabel;
+use crate::config::Granularity;
+use crate::domain::ServiceId;
+
+/// How long an address is remembered after its pod let go of it, to
+/// attribute queries from lagging or replayed logs.
+const HISTORY_RETENTION: Duration = Duration::hours(1);
+
+/// Pods of the cluster, kept up to date by a watch, with the history of which
+/// pod owned every address in `status.podIPs`, so attributing a query is an
+/// in-memory lookup even after the address was handed to another pod. Node
+/// addresses are indexed as well, for hostNetwork pods and node agents.
+#[derive(Clone)]
+pub struct PodIndex {
+    /// Intermediate owners, walked up to the workload of a pod. Only their
+    /// ownerReferences are needed, so only their metadata is watched.
+    replica_sets: Store<PartialObjectMeta<ReplicaSet>>,
+    jobs: Store<PartialObjectMeta<Job>>,
+    ips: Arc<RwLock<IpHistory>>,
+    /// InternalIP and ExternalIP address -> node name.
+    nodes: Arc<RwLock<HashMap<IpAddr, String>>>,
+    ready: watch::Receiver<bool>,
+}
+
+impl PodIndex {
+    pub fn start(client: &Client) -> Self {
+        let ips = Arc::new(RwLock::new(IpHistory::default()));
+        let (ready_tx, ready) = watch::channel(false);
+        // Pods are not kept, the history takes what it needs from them.
+        let stream =
+            watcher(Api::<Pod>::all(client.clone()), watcher::Config::default()).default_backoff();
+
+        let history = ips.clone();
+        tokio::spawn(async move {
+            let mut stream = std::pin::pin!(stream);
+            // Pods of a re-list, pods missing from it went away unseen.
+            let mut relisted = HashSet::new();
+            while let Some(event) = stream.next().await {
+                let now = Utc::now();
+                match event {
+                    Ok(Event::Apply(pod)) => history.write().unwrap().apply(&pod, now),
+                    Ok(Event::Delete(pod)) => history
+                        .write()
+                        .unwrap()
+                        .release(&ObjectRef::from_obj(&pod), now),
+                    Ok(Event::Init) => relisted.clear(),
+                    Ok(Event::InitApply(pod)) => {
+                        relisted.insert(ObjectRef::from_obj(&pod));
+                        history.write().unwrap().apply(&pod, now);
+                    }
+                    Ok(Event::InitDone) => {
+                        history
+                            .write()
+                            .unwrap()
+                            .resync(&std::mem::take(&mut relisted), now);
+                        ready_tx.send_replace(true);
+                    }
+                    Err(err) => log::error!("Pod watch failed: {}", err),
+                }
+            }
+        });
+
+        let nodes = Arc::new(RwLock::new(HashMap::new()));
+        let stream =
+            watcher(Api::<Node>::all(client.clone()), watcher::Config::default()).default_backoff();
+
+        let addresses = nodes.clone();
+        tokio::spawn(async move {
+            let mut stream = std::pin::pin!(stream);
+            let mut relisted = HashMap::new();
+            while let Some(event) = stream.next().await {
+                match event {
+                    Ok(Event::Apply(node)) => index_node(&mut addresses.write().unwrap(), &node),
+                    Ok(Event::Delete(node)) => {
+                        let name = node.metadata.name.unwrap_or_default();
+                        addresses.write().unwrap().retain(|_, owner| *owner != name);
+                    }
+                    Ok(Event::Init) => relisted.clear(),
+                    Ok(Event::InitApply(node)) => index_node(&mut relisted, &node),
+                    Ok(Event::InitDone) => {
+                        *addresses.write().unwrap() = std::mem::take(&mut relisted);
+                    }
+                    Err(err) => log::error!("Node watch failed: {}", err),
+                }
+            }
+        });
+
+        Self {
+            replica_sets: reflect_metadata(Api::all(client.clone())),
+            jobs: reflect_metadata(Api::all(client.clone())),
+            ips,
+            nodes,
+            ready,
+        }
+    }
+
+    /// Waits for the first complete listing of the pods. Nodes are not
+    /// waited for, node clients are kept by address until they are listed.
+    pub async fn ready(&self) {
+        let _ = self.ready.clone().wait_for(|ready| *ready).await;
+    }
+
+    /// The pod that owned the address at the time, or the current owner
+    /// when the time is unknown.
+    fn pod(&self, ip: IpAddr, at: Option<DateTime<Utc>>) -> Option<Arc<PodMeta>> {
+        self.ips.read().unwrap().owner(ip, at)
+    }
+
+    /// Keys of the pod that owned the address at the time, for names
+    /// pointing at it.
+    pub fn target(&self, ip: IpAddr, at: Option<DateTime<Utc>>) -> Option<ClientKeys> {
+        self.pod(ip, at).map(|pod| self.pod_keys(&pod))
+    }
+
+    /// Name of the node with the address.
+    pub fn node(&self, ip: IpAddr) -> Option<String> {
+        self.nodes.read().unwrap().get(&ip.to_canonical()).cloned()
+    }
+
+    /// Keys of the client that owned the address at the time. Queries from a
+    /// node address come from its only hostNetwork pod using the cluster DNS,
+    /// or else from the node itself (kubelet, agents running outside of
+    /// pods, hostNetwork pods resolving through the node, ...).
+    pub fn client(&self, ip: IpAddr, at: Option<DateTime<Utc>>) -> Option<ClientKeys> {
+        if let Some(pod) = self.pod(ip, at) {
+            return Some(self.pod_keys(&pod));
+        }
+        let node = self.node(ip)?;
+        let host_pod = {
+            let history = self.ips.read().unwrap();
+            match history.host_network.get(&node) {
+                Some(pods) if pods.len() == 1 => {
+                    pods.iter().next().and_then(|key| history.pod(key))
+                }
+                _ => None,
+            }
+        };
+        Some(match host_pod {
+            Some(pod) => self.pod_keys(&pod),
+            None => ClientKeys::node(&node),
+        })
+    }
+
+    fn pod_keys(&self, pod: &PodMeta) -> ClientKeys {
+        let namespace = pod.namespace().to_string();
+        let (kind, workload) = self.workload(pod);
+        ClientKeys {
+            pod: format!("{}/{}", namespace, pod.key.name),
+            workload: format!("{}/{}/{}", namespace, kind.to_ascii_lowercase(), workload),
+            namespace,
+            node: false,
+        }
+    }
+
+    /// Kind and name of the controller at the top of the pod's
+    /// ownerReferences, the pod itself when nothing controls it.
+    fn workload(&self, pod: &PodMeta) -> (String, String) {
+        let Some(owner) = &pod.controller else {
+            return ("Pod".to_string(), pod.key.name.clone());
+        };
+        let namespace = pod.namespace();
+
+        // ReplicaSets belong to Deployments and Jobs to CronJobs, the other
+        // controllers (StatefulSet, DaemonSet, ...) own their pods directly.
+        let parent = match owner.kind.as_str() {
+            "ReplicaSet" => match self
+                .replica_sets
+                .get(&ObjectRef::new(&owner.name).within(namespace))
+            {
+                Some(replica_set) => controller(&replica_set.metadata).cloned(),
+                None => deployment_of(pod, &owner.name),
+            },
+            "Job" => self
+                .jobs
+                .get(&ObjectRef::new(&owner.name).within(namespace))
+                .and_then(|job| controller(&job.metadata).cloned()),
+            _ => None,
+        };
+        match parent {
+            Some(parent) => (parent.kind, parent.name),
+            None => (owner.kind.clone(), owner.name.clone()),
+        }
+    }
+
+    /// Finds the pod publishing `<hostname>.<subdomain>`, which for
+    /// StatefulSets is simply the pod name, and returns its keys.
+    pub fn member(&self, hostname: &str, subdomain: &ServiceId) -> Option<ClientKeys> {
+        let pod = {
+            let history = self.ips.read().unwrap();
+            history.pod(history.members.get(subdomain)?.get(hostname)?)?
+        };
+        Some(self.pod_keys(&pod))
+    }
+}
+
+/// Keys a client is recorded under, from the finest granularity to the
+/// coarsest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientKeys {
+    /// `<namespace>/<name>` of the pod.
+    pub(crate) pod: String,
+    /// `<namespace>/<kind>/<name>` of the workload running the pod.
+    pub(crate) workload: String,
+    pub(crate) namespace: String,
+    /// Whether the client is a node rather than a pod.
+    pub(crate) node: bool,
+}
+
+impl ClientKeys {
+    /// A client outside of the cluster's pods and nodes, or one that is
+    /// gone, kept by address and grouped by the label of its range.
+    pub fn address(ip: IpAddr, label: Option<&CidrLabel>) -> Self {
+        let group = label.map_or_else(|| ip.to_string(), |label| format!("cidr/{}", label.name));
+        Self {
+            pod: ip.to_string(),
+            workload: group.clone(),
+            namespace: group,
+            node: false,
+        }
+    }
+
+    /// A node, keyed `node/<name>` at every granularity.
+    fn node(name: &str) -> Self {
+        let key = format!("node/{}", name);
+        Self {
+            pod: key.clone(),
+            workload: key.clone(),
+            namespace: key,
+            node: true,
+        }
+    }
+
+    pub fn get(&self, granularity: Granularity) -> &str {
+        match granularity {
+            Granularity::Pod => &self.pod,
+            Granularity::Workload => &self.workload,
+            Granularity::Namespace => &self.namespace,
+        }
+    }
+
+    /// The keys left when recording at the granularity, the finer ones take
+    /// the recorded key.
+    pub fn coarsen(mut self, granularity: Granularity) -> Self {
+        if granularity == Granularity::Namespace {
+            self.workload = self.namespace.clone();
+        }
+        if granularity != Granularity::Pod {
+            self.pod = self.workload.clone();
+        }
+        self
+    }
+}
+
+/// What attributing a query needs of a pod, kept instead of the whole
+/// object. Pods are keyed `<namespace>/<name>`, names are only unique within
+/// a namespace.
+#[derive(Debug)]
+struct PodMeta {
+    key: ObjectRef<Pod>,
+    controller: Option<OwnerReference>,
+    /// The `pod-template-hash` label, set on the pods of Deployments.
+    template_hash: Option<String>,
+}
+
+impl PodMeta {
+    fn new(pod: &Pod) -> Self {
+        Self {
+            key: ObjectRef::from_obj(pod),
+            controller: controller(&pod.metadata).cloned(),
+            template_hash: pod
+                .metadata
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get("pod-template-hash"))
+                .cloned(),
+        }
+    }
+
+    fn namespace(&self) -> &str {
+        self.key.namespace.as_deref().unwrap_or_default()
+    }
+}
+
+fn controller(meta: &ObjectMeta) -> Option<&OwnerReference> {
+    meta.owner_references
+        .iter()
+        .flatten()
+        .find(|owner| owner.controller == Some(true))
+}
+
+/// The Deployment behind a ReplicaSet that is gone, from the
+/// `pod-template-hash` suffix of the ReplicaSet name.
+fn deployment_of(pod: &PodMeta, replica_set: &str) -> Option<OwnerReference> {
+    let hash = pod.template_hash.as_deref()?;
+    let name = replica_set.strip_suffix(hash)?.strip_suffix('-')?;
+    Some(OwnerReference {
+        kind: "Deployment".to_string(),
+        name: name.to_string(),
+        ..Default::default()
+    })
+}
+
+/// A pod holding an address, from `from` until `to`.
+struct Ownership {
+    pod: Arc<PodMeta>,
+    from: DateTime<Utc>,
+    /// `None` while the pod still holds the address.
+    to: Option<DateTime<Utc>>,
+}
+
+impl Ownership {
+    fn covers(&self, at: DateTime<Utc>) -> bool {
+        self.from <= at && self.to.is_none_or(|to| at < to)
+    }
+}
+
+/// Owners of every address, oldest first, and the addresses each pod
+/// currently holds. Also keeps the pods publishing headless service
+/// records, which come and go with them.
+#[derive(Default)]
+struct IpHistory {
+    /// Pods that exist, as far as the watch knows.
+    pods: HashMap<ObjectRef<Pod>, Arc<PodMeta>>,
+    by_ip: HashMap<IpAddr, Vec<Ownership>>,
+    by_pod: HashMap<ObjectRef<Pod>, Vec<IpAddr>>,
+    /// Subdomain (headless Service) -> hostname -> pod.
+    members: HashMap<ServiceId, HashMap<String, ObjectRef<Pod>>>,
+    /// The reverse of `members`.
+    member_of: HashMap<ObjectRef<Pod>, (ServiceId, String)>,
+    /// Node -> hostNetwork pods running on it that resolve through the
+    /// cluster DNS (`dnsPolicy: ClusterFirstWithHostNet`). They share the
+    /// node's addresses with the kubelet and every other host process.
+    host_network: HashMap<String, HashSet<ObjectRef<Pod>>>,
+    last_prune: Option<DateTime<Utc>>,
+}
+
+impl IpHistory {
+    /// The pod that owned the address at the time, or the current owner
+    /// when the time is unknown.
+    fn owner(&self, ip: IpAddr, at: Option<DateTime<Utc>>) -> Option<Arc<PodMeta>> {
+        let owners = self.by_ip.get(&ip.to_canonical())?;
+        let owner = match at {
+            Some(at) => owners.iter().rev().find(|owner| owner.covers(at)),
+            None => owners.iter().rev().find(|owner| owner.to.is_none()),
+        };
+        owner.map(|owner| owner.pod.clone())
+    }
+
+    fn pod(&self, key: &ObjectRef<Pod>) -> Option<Arc<PodMeta>> {
+        self.pods.get(key).cloned()
+    }
+
+    fn apply(&mut self, pod: &Pod, now: DateTime<Utc>) {
+        let meta = Arc::new(PodMeta::new(pod));
+        let key = meta.key.clone();
+        self.pods.insert(key.clone(), meta.clone());
+        // Finished pods keep their addresses in the status after the
+        // network has handed them out again, and so do terminating pods
+        // that may still report `Running`.
+        let finished = pod.metadata.deletion_timestamp.is_some()
+            || pod
+                .status
+                .as_ref()
+                .and_then(|status| status.phase.as_deref())
+                .is_some_and(|phase| phase == "Succeeded" || phase == "Failed");
+        let spec = pod.spec.as_ref();
+        let host_network = spec.and_then(|spec| spec.host_network) == Some(true);
+        let cluster_dns =
+            spec.and_then(|spec| spec.dns_policy.as_deref()) == Some("ClusterFirstWithHostNet");
+        for pods in self.host_network.values_mut() {
+            pods.remove(&key);
+        }
+        if host_network
+            && cluster_dns
+            && !finished
+            && let Some(node) = spec.and_then(|spec| spec.node_name.clone())
+        {
+            self.host_network
+                .entry(node)
+                .or_default()
+                .insert(key.clone());
+        }
+        if finished {
+            self.leave(&key);
+        } else {
+            self.join(&key, pod);
+        }
+        let ips = if finished || host_network {
+            Vec::new()
+        } else {
+            pod_ips(pod)
+        };
+
+        let held = self.by_pod.remove(&key).unwrap_or_default();
+        for ip in held.iter().filter(|ip| !ips.contains(ip)) {
+            self.close(*ip, &key, now);
+        }
+        // A late update of a pod a newer owner took the address from does
+        // not take it back.
+        let ips: Vec<IpAddr> = ips
+            .into_iter()
+            .filter(|ip| {
+                let owners = self.by_ip.get(ip).into_iter().flatten();
+                !owners
+                    .skip_while(|owner| owner.pod.key != key || owner.to.is_none())
+                    .any(|owner| owner.pod.key != key)
+            })
+            .collect();
+
+        // The address is assigned when the kubelet starts the pod.
+        let from = pod
+            .status
+            .as_ref()
+            .and_then(|status| status.start_time.as_ref())
+            .or(pod.metadata.creation_timestamp.as_ref())
+            .map_or(now, |time| time.0);
+        for ip in &ips {
+            let owners = self.by_ip.entry(*ip).or_default();
+            if let Some(current) = owners
+                .iter_mut()
+                .find(|owner| owner.to.is_none() && owner.pod.key == key)
+            {
+                current.pod = meta.clone();
+                continue;
+            }
+            // A deletion already seen may have let go of the address after
+            // the new pod started, it only gets the address from then on.
+            let released = owners.iter().filter_map(|owner| owner.to).max();
+            let from = released.map_or(from, |released| from.max(released));
+            // Whoever still holds the address is gone since the new pod
+            // started, even if its deletion has not been seen yet.
+            for owner in owners.iter_mut().filter(|owner| owner.to.is_none()) {
+                owner.to = Some(from.min(now).max(owner.from));
+            }
+            owners.push(Ownership {
+                pod: meta.clone(),
+                from,
+                to: None,
+            });
+        }
+        if !ips.is_empty() {
+            self.by_pod.insert(key, ips);
+        }
+        self.prune(now);
+    }
+
+    /// Ends the pod's hold on all its addresses.
+    fn release(&mut self, key: &ObjectRef<Pod>, now: DateTime<Utc>) {
+        self.pods.remove(key);
+        for pods in self.host_network.values_mut() {
+            pods.remove(key);
+        }
+        self.leave(key);
+        for ip in self.by_pod.remove(key).into_iter().flatten() {
+            self.close(ip, key, now);
+        }
+        self.prune(now);
+    }
+
+    /// Completes a listing whose pods were applied, releasing the pods that
+    /// are not in it.
+    fn resync(&mut self, listed: &HashSet<ObjectRef<Pod>>, now: DateTime<Utc>) {
+        let gone: Vec<_> = self
+            .pods
+            .keys()
+            .filter(|key| !listed.contains(key))
+            .cloned()
+            .collect();
+        for key in gone {
+            self.release(&key, now);
+        }
+    }
+
+    /// Indexes the `<hostname>.<subdomain>` record the pod publishes, if any.
+    fn join(&mut self, key: &ObjectRef<Pod>, pod: &Pod) {
+        self.leave(key);
+        let Some(spec) = &pod.spec else {
+            return;
+        };
+        let (Some(hostname), Some(subdomain), Some(namespace)) =
+            (&spec.hostname, &spec.subdomain, &pod.metadata.namespace)
+        else {
+            return;
+        };
+        let service = ServiceId::new(subdomain, namespace);
+        let hostname = hostname.to_ascii_lowercase();
+        self.members
+            .entry(service.clone())
+            .or_default()
+            .insert(hostname.clone(), key.clone());
+        self.member_of.insert(key.clone(), (service, hostname));
+    }
+
+    fn leave(&mut self, key: &ObjectRef<Pod>) {
+        let Some((service, hostname)) = self.member_of.remove(key) else {
+            return;
+        };
+        if let Some(members) = self.members.get_mut(&service) {
+            // Another pod may have taken the name over since.
+            if members.get(&hostname) == Some(key) {
+                members.remove(&hostname);
+            }
+            if members.is_empty() {
+                self.members.remove(&service);
+            }
+        }
+    }
+
+    fn close(&mut self, ip: IpAddr, key: &ObjectRef<Pod>, now: DateTime<Utc>) {
+        let owners = self.by_ip.get_mut(&ip).into_iter().flatten();
+        for owner in owners.filter(|owner| owner.to.is_none()) {
+            if owner.pod.key == *key {
+                owner.to = Some(now);
+            }
+        }
+    }
+
+    /// Forgets owners that let go of their address too long ago.
+    fn prune(&mut self, now: DateTime<Utc>) {
+        if self
+            .last_prune
+            .is_some_and(|last| now - last < Duration::minutes(1))
+        {
+            return;
+        }
+        self.last_prune = Some(now);
+        let horizon = now - HISTORY_RETENTION;
+        self.by_ip.retain(|_, owners| {
+            owners.retain(|owner| owner.to.is_none_or(|to| to > horizon));
+            !owners.is_empty()
+        });
+    }
+}
+
+/// Indexes the InternalIP and ExternalIP addresses of the node.
+fn index_node(addresses: &mut HashMap<IpAddr, String>, node: &Node) {
+    let Some(name) = node.metadata.name.clone() else {
+        return;
+    };
+    addresses.retain(|_, owner| *owner != name);
+    let node_addresses = node
+        .status
+        .iter()
+        .flat_map(|status| status.addresses.iter().flatten())
+        .filter(|address| address.type_ == "InternalIP" || address.type_ == "ExternalIP")
+        .filter_map(|address| address.address.parse::<IpAddr>().ok());
+    for ip in node_addresses {
+        addresses.insert(ip.to_canonical(), name.clone());
+    }
+}
+
+/// Every address in `status.podIPs`, falling back to `status.podIP`.
+fn pod_ips(pod: &Pod) -> Vec<IpAddr> {
+    let Some(status) = &pod.status else {
+        return Vec::new();
+    };
+    let ips = status
+        .pod_ips
+        .iter()
+        .flatten()
+        .map(|pod_ip| pod_ip.ip.as_str())
+        .chain(status.pod_ip.as_deref())
+        .filter_map(|ip| ip.parse::<IpAddr>().ok())
+        .map(|ip| ip.to_canonical());
+    let mut unique = Vec::new();
+    for ip in ips {
+        if !unique.contains(&ip) {
+            unique.push(ip);
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{PodSpec, PodStatus};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use kube::runtime::reflector;
+
+    const IP: &str = "10.244.0.5";
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn ip() -> IpAddr {
+        IP.parse().unwrap()
+    }
+
+    fn pod(name: &str, started: i64) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some("shop".to_string()),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                phase: Some("Running".to_string()),
+                pod_ip: Some(IP.to_string()),
+                start_time: Some(Time(at(started))),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn key(name: &str) -> ObjectRef<Pod> {
+        ObjectRef::new(name).within("shop")
+    }
+
+    fn owner(history: &IpHistory, secs: Option<i64>) -> Option<String> {
+        history
+            .owner(ip(), secs.map(at))
+            .map(|pod| pod.key.name.clone())
+    }
+
+    #[test]
+    fn apply_owns_the_address_from_the_start_of_the_pod() {
+        let mut history = IpHistory::default();
+        history.apply(&pod("api-1", 10), at(20));
+        assert_eq!(owner(&history, Some(9)), None);
+        assert_eq!(owner(&history, Some(10)).as_deref(), Some("api-1"));
+        assert_eq!(owner(&history, Some(1_000)).as_deref(), Some("api-1"));
+        assert_eq!(owner(&history, None).as_deref(), Some("api-1"));
+
+        // Updates of the same pod keep its ownership
+        history.apply(&pod("api-1", 10), at(30));
+        assert_eq!(history.by_ip[&ip()].len(), 1);
+    }
+
+    #[test]
+    fn release_keeps_the_owner_for_earlier_queries() {
+        let mut history = IpHistory::default();
+        history.apply(&pod("api-1", 10), at(20));
+        history.release(&key("api-1"), at(50));
+        assert_eq!(owner(&history, Some(49)).as_deref(), Some("api-1"));
+        assert_eq!(owner(&history, Some(50)), None);
+        assert_eq!(owner(&history, None), None);
+        assert!(history.by_pod.is_empty());
+    }
+
+    #[test]
+    fn finished_and_host_network_pods_hold_no_address() {
+        let mut history = IpHistory::default();
+        history.apply(&pod("api-1", 10), at(20));
+        let mut finished = pod("api-1", 10);
+        finished.status.as_mut().unwrap().phase = Some("Succeeded".to_string());
+        history.apply(&finished, at(30));
+        assert_eq!(owner(&history, Some(29)).as_deref(), Some("api-1"));
+        assert_eq!(owner(&history, Some(30)), None);
+
+        let mut agent = pod("agent", 10);
+        agent.spec = Some(PodSpec {
+            host_network: Some(true),
+            dns_policy: Some("ClusterFirstWithHostNet".to_string()),
+            node_name: Some("node-a".to_string()),
+            ..Default::default()
+        });
+        history.apply(&agent, at(40));
+        assert_eq!(owner(&history, Some(40)), None);
+        assert!(history.host_network["node-a"].contains(&key("agent")));
+    }
+
+    #[test]
+    fn reuse_after_a_seen_delete_starts_at_the_release() {
+        let mut history = IpHistory::default();
+        history.apply(&pod("api-1", 10), at(20));
+        history.release(&key("api-1"), at(100));
+        // The new pod started before the old one let go of the address
+        history.apply(&pod("api-2", 90), at(110));
+        assert_eq!(owner(&history, Some(95)).as_deref(), Some("api-1"));
+        assert_eq!(owner(&history, Some(100)).as_deref(), Some("api-2"));
+        assert_eq!(owner(&history, None).as_deref(), Some("api-2"));
+        // The old owner is left as it was
+        assert_eq!(history.by_ip[&ip()][0].to, Some(at(100)));
+    }
+
+    #[test]
+    fn reuse_before_the_delete_is_seen_closes_the_old_owner() {
+        let mut history = IpHistory::default();
+        history.apply(&pod("api-1", 10), at(20));
+        history.apply(&pod("api-2", 90), at(110));
+        assert_eq!(owner(&history, Some(89)).as_deref(), Some("api-1"));
+        assert_eq!(owner(&history, Some(95)).as_deref(), Some("api-2"));
+        assert_eq!(owner(&history, Some(110)).as_deref(), Some("api-2"));
+
+        // The late delete does not move the old owner again
+        history.release(&key("api-1"), at(120));
+        assert_eq!(history.by_ip[&ip()][0].to, Some(at(90)));
+        assert_eq!(owner(&history, None).as_deref(), Some("api-2"));
+    }
+
+    #[test]
+    fn late_update_of_the_old_owner_does_not_take_the_address_back() {
+        let mut history = IpHistory::default();
+        history.apply(&pod("api-1", 10), at(20));
+        history.apply(&pod("api-2", 90), at(110));
+
+        // api-1 terminating, its status not updated yet
+        history.apply(&pod("api-1", 10), at(115));
+        assert_eq!(owner(&history, None).as_deref(), Some("api-2"));
+        assert_eq!(owner(&history, Some(95)).as_deref(), Some("api-2"));
+        assert_eq!(owner(&history, Some(89)).as_deref(), Some("api-1"));
+        assert!(!history.by_pod.contains_key(&key("api-1")));
+
+        let mut deleted = pod("api-2", 90);
+        deleted.metadata.deletion_timestamp = Some(Time(at(150)));
+        history.apply(&deleted, at(120));
+        assert_eq!(owner(&history, None), None);
+        assert_eq!(owner(&history, Some(119)).as_deref(), Some("api-2"));
+    }
+
+    #[test]
+    fn resync_releases_pods_missing_from_the_listing() {
+        let mut history = IpHistory::default();
+        history.apply(&pod("api-1", 10), at(20));
+        let mut other = pod("web-1", 10);
+        other.status.as_mut().unwrap().pod_ip = Some("10.244.0.6".to_string());
+        history.apply(&other, at(60));
+        history.resync(&HashSet::from([key("web-1")]), at(60));
+
+        assert_eq!(owner(&history, Some(59)).as_deref(), Some("api-1"));
+        assert_eq!(owner(&history, None), None);
+        assert!(history.by_pod.contains_key(&key("web-1")));
+        assert!(!history.by_pod.contains_key(&key("api-1")));
+    }
+
+    #[test]
+    fn prunes_owners_gone_longer_than_the_retention() {
+        let mut history = IpHistory::default();
+        history.apply(&pod("api-1", 10), at(20));
+        history.release(&key("api-1"), at(100));
+        history.prune(at(100) + HISTORY_RETENTION + Duration::minutes(2));
+        assert!(history.by_ip.is_empty());
+    }
+
+    fn member_pod(name: &str, hostname: &str) -> Pod {
+        let mut pod = pod(name, 10);
+        pod.spec = Some(PodSpec {
+            hostname: Some(hostname.to_string()),
+            subdomain: Some("db".to_string()),
+            ..Default::default()
+        });
+        pod
+    }
+
+    fn member(history: &IpHistory, hostname: &str) -> Option<String> {
+        history
+            .members
+            .get(&ServiceId::new("db", "shop"))?
+            .get(hostname)
+            .map(|key| key.name.clone())
+    }
+
+    #[test]
+    fn indexes_headless_members_while_their_pod_runs() {
+        let mut history = IpHistory::default();
+        history.apply(&member_pod("db-0", "db-0"), at(20));
+        history.apply(&member_pod("custom", "primary"), at(20));
+        assert_eq!(member(&history, "db-0").as_deref(), Some("db-0"));
+        assert_eq!(member(&history, "primary").as_deref(), Some("custom"));
+
+        // A new pod taking the name over keeps it when the old one goes
+        history.apply(&member_pod("replacement", "primary"), at(30));
+        history.release(&key("custom"), at(40));
+        assert_eq!(member(&history, "primary").as_deref(), Some("replacement"));
+
+        history.release(&key("db-0"), at(40));
+        assert_eq!(member(&history, "db-0"), None);
+        history.resync(&HashSet::new(), at(50));
+        assert!(history.members.is_empty());
+        assert!(history.member_of.is_empty());
+    }
+
+    const NODE_IP: &str = "192.168.1.10";
+
+    fn host_pod(name: &str, dns_policy: &str) -> Pod {
+        let mut pod = pod(name, 10);
+        pod.spec = Some(PodSpec {
+            host_network: Some(true),
+            dns_policy: Some(dns_policy.to_string()),
+            node_name: Some("node-a".to_string()),
+            ..Default::default()
+        });
+        pod.status.as_mut().unwrap().pod_ip = Some(NODE_IP.to_string());
+        pod
+    }
+
+    /// An index of the pods, with `node-a` on `NODE_IP`.
+    fn pod_index(pods: Vec<Pod>) -> PodIndex {
+        let mut history = IpHistory::default();
+        for pod in pods {
+            history.apply(&pod, at(20));
+        }
+        let nodes = HashMap::from([(NODE_IP.parse().unwrap(), "node-a".to_string())]);
+        PodIndex {
+            replica_sets: reflector::store().0,
+            jobs: reflector::store().0,
+            ips: Arc::new(RwLock::new(history)),
+            nodes: Arc::new(RwLock::new(nodes)),
+            ready: watch::channel(true).1,
+        }
+    }
+
+    fn client(index: &PodIndex, ip: &str) -> Option<String> {
+        index
+            .client(ip.parse().unwrap(), Some(at(30)))
+            .map(|client| client.pod)
+    }
+
+    #[test]
+    fn attributes_pod_addresses_to_pods() {
+        let index = pod_index(vec![pod("api-1", 10)]);
+        let keys = index.client(ip(), Some(at(30))).unwrap();
+        assert_eq!(keys.pod, "shop/api-1");
+        assert_eq!(keys.workload, "shop/pod/api-1");
+        assert!(!keys.node);
+        assert_eq!(client(&index, "10.244.9.9"), None);
+    }
+
+    #[test]
+    fn attributes_node_addresses_to_the_node() {
+        let keys = pod_index(Vec::new())
+            .client(NODE_IP.parse().unwrap(), Some(at(30)))
+            .unwrap();
+        assert_eq!(keys.pod, "node/node-a");
+        assert_eq!(keys.namespace, "node/node-a");
+        assert!(keys.node);
+    }
+
+    #[test]
+    fn attributes_node_addresses_to_the_only_host_network_pod_using_cluster_dns() {
+        let index = pod_index(vec![
+            host_pod("agent", "ClusterFirstWithHostNet"),
+            host_pod("exporter", "ClusterFirst"),
+        ]);
+        assert_eq!(client(&index, NODE_IP).as_deref(), Some("shop/agent"));
+    }
+
+    #[test]
+    fn keeps_node_addresses_on_the_node_when_ambiguous() {
+        let index = pod_index(vec![
+            host_pod("agent", "ClusterFirstWithHostNet"),
+            host_pod("proxy", "ClusterFirstWithHostNet"),
+        ]);
+        assert_eq!(client(&index, NODE_IP).as_deref(), Some("node/node-a"));
+
+        let resolving_through_node = pod_index(vec![host_pod("exporter", "Default")]);
+        assert_eq!(
+            client(&resolving_through_node, NODE_IP).as_deref(),
+            Some("node/node-a")
+        );
+    }
+
+    #[test]
+    fn finds_headless_members_by_hostname() {
+        let index = pod_index(vec![member_pod("db-0", "db-0")]);
+        let keys = index.member("db-0", &ServiceId::new("db", "shop")).unwrap();
+        assert_eq!(keys.pod, "shop/db-0");
+        assert_eq!(index.member("db-0", &ServiceId::new("db", "other")), None);
+        assert_eq!(index.member("db-1", &ServiceId::new("db", "shop")), None);
+    }
+
+    fn owned_by(kind: &str, name: &str) -> Option<Vec<OwnerReference>> {
+        Some(vec![OwnerReference {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            controller: Some(true),
+            ..Default::default()
+        }])
+    }
+
+    #[test]
+    fn walks_replica_sets_up_to_their_deployment() {
+        let mut index = pod_index(Vec::new());
+        let (store, mut writer) = reflector::store();
+        writer.apply_watcher_event(&Event::Apply(PartialObjectMeta::<ReplicaSet> {
+            metadata: ObjectMeta {
+                name: Some("api-7d4b9c".to_string()),
+                namespace: Some("shop".to_string()),
+                owner_references: owned_by("Deployment", "api"),
+                ..Default::default()
+            },
+            ..Default::default()
+        }));
+        index.replica_sets = store;
+
+        let mut pod = pod("api-7d4b9c-x2kq9", 10);
+        pod.metadata.owner_references = owned_by("ReplicaSet", "api-7d4b9c");
+        assert_eq!(
+            index.pod_keys(&PodMeta::new(&pod)).workload,
+            "shop/deployment/api"
+        );
+
+        // A ReplicaSet that is gone, from the pod-template-hash label
+        pod.metadata.owner_references = owned_by("ReplicaSet", "web-5f6b7");
+        pod.metadata.labels = Some([("pod-template-hash".to_string(), "5f6b7".to_string())].into());
+        assert_eq!(
+            index.pod_keys(&PodMeta::new(&pod)).workload,
+            "shop/deployment/web"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use crate::suffixes;
+
+/// Public suffix of a name, for example `co.uk` or the private
+/// `s3.amazonaws.com`. Names not covered by any rule fall back to their TLD.
+pub fn public_suffix(name: &str) -> &str {
+    // Candidates go from the whole name down to the TLD, so the first rule
+    // that matches is the longest one.
+    let psl = suffixes::public_suffixes();
+    let mut candidate = name;
+    loop {
+        let parent = candidate.split_once('.').map(|(_, parent)| parent);
+        if psl.exceptions.contains(candidate) {
+            return parent.unwrap_or(candidate);
+        }
+        if psl.rules.contains(candidate)
+            || parent.is_some_and(|parent| psl.wildcards.contains(parent))
+        {
+            return candidate;
+        }
+        match parent {
+            Some(parent) => candidate = parent,
+            None => return candidate,
+        }
+    }
+}
+
+/// The public suffix plus one label (eTLD+1), `None` when the name is a
+/// public suffix itself.
+pub fn registrable_domain(name: &str) -> Option<&str> {
+    let suffix = public_suffix(name);
+    let prefix = name.strip_suffix(suffix)?.strip_suffix('.')?;
+    let start = prefix.rfind('.').map_or(0, |dot| dot + 1);
+    Some(&name[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_icann_and_private_suffixes() {
+        assert_eq!(public_suffix("www.example.co.uk"), "co.uk");
+        assert_eq!(
+            registrable_domain("www.example.co.uk"),
+            Some("example.co.uk")
+        );
+        assert_eq!(public_suffix("foo.s3.amazonaws.com"), "s3.amazonaws.com");
+        assert_eq!(
+            registrable_domain("foo.s3.amazonaws.com"),
+            Some("foo.s3.amazonaws.com")
+        );
+    }
+
+    #[test]
+    fn applies_wildcard_rules() {
+        // *.ck
+        assert_eq!(public_suffix("www.example.co.ck"), "co.ck");
+        assert_eq!(
+            registrable_domain("www.example.co.ck"),
+            Some("example.co.ck")
+        );
+        // *.compute.amazonaws.com
+        assert_eq!(
+            public_suffix("ec2-1-2-3-4.eu-west-1.compute.amazonaws.com"),
+            "eu-west-1.compute.amazonaws.com"
+        );
+    }
+
+    #[test]
+    fn applies_exception_rules() {
+        // !www.ck
+        assert_eq!(public_suffix("www.ck"), "ck");
+        assert_eq!(registrable_domain("www.ck"), Some("www.ck"));
+        // *.kawasaki.jp, !city.kawasaki.jp
+        assert_eq!(public_suffix("www.city.kawasaki.jp"), "kawasaki.jp");
+        assert_eq!(
+            registrable_domain("www.city.kawasaki.jp"),
+            Some("city.kawasaki.jp")
+        );
+        assert_eq!(
+            public_suffix("www.example.kawasaki.jp"),
+            "example.kawasaki.jp"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_tld() {
+        assert_eq!(public_suffix("host.example.unlisted"), "unlisted");
+        assert_eq!(
+            registrable_domain("host.example.unlisted"),
+            Some("example.unlisted")
+        );
+    }
+
+    #[test]
+    fn has_no_registrable_domain_for_a_suffix() {
+        assert_eq!(registrable_domain("co.uk"), None);
+        assert_eq!(registrable_domain("uk"), None);
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(public_suffix("WWW.Example.CO.UK"), "CO.UK");
+        assert_eq!(
+            registrable_domain("WWW.Example.CO.UK"),
+            Some("Example.CO.UK")
+        );
+    }
+}
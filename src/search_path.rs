@@ -0,0 +1,647 @@
+use k8s_openapi::api::core::v1::{PodDNSConfig, PodDNSConfigOption};
+use k8s_openapi::chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::dns_event::DnsQueryEvent;
+
+/// How long an NXDOMAIN waits for the query it is a search-path expansion
+/// of, and how far apart in time the queries of one lookup can be.
+const SEARCH_WINDOW: TimeDelta = TimeDelta::seconds(5);
+/// How long an answer waits for expansions that arrive after it, from
+/// another replica for example.
+const ANSWER_HOLD: TimeDelta = TimeDelta::seconds(2);
+/// Queries held per client, the oldest are released early past it.
+const MAX_HELD: usize = 4096;
+/// Intended names kept per client in the report.
+const MAX_NAMES: usize = 32;
+
+/// A query held back by the tracker.
+struct Pending {
+    held: Held,
+    at: DateTime<Utc>,
+    /// The names the query may have been written as, lowercase, with whether
+    /// they went through a search domain. Only the name it concluded once
+    /// that is known.
+    written_as: Vec<(String, bool)>,
+    /// Whether the name is in one of the internal zones.
+    internal: bool,
+}
+
+impl Pending {
+    /// Query name without the trailing dot, compared ignoring ASCII case.
+    fn name(&self) -> &str {
+        self.held.event.name.trim_end_matches('.')
+    }
+
+    fn is_nxdomain(&self) -> bool {
+        self.held.event.rcode == "NXDOMAIN"
+    }
+
+    /// NXDOMAINs may be expansions of a later query, answers only wait for
+    /// the expansions of their own lookup.
+    fn expires(&self) -> DateTime<Utc> {
+        self.at
+            + if self.is_nxdomain() {
+                SEARCH_WINDOW
+            } else {
+                ANSWER_HOLD
+            }
+    }
+
+    /// The name this query was written as, if `expansion` expands it.
+    fn intended(&self, expansion: &str) -> Option<&(String, bool)> {
+        self.written_as
+            .iter()
+            .find(|(intended, _)| is_expansion(expansion, intended))
+    }
+}
+
+/// A query held back by the tracker, returned once its lookup is complete.
+pub struct Held {
+    pub(crate) replica: Arc<str>,
+    pub(crate) event: DnsQueryEvent,
+    /// The lookup this query concluded, if it was the name as written.
+    pub(crate) lookup: Option<Lookup>,
+}
+
+/// A logical lookup, with the search-path expansions collapsed into it.
+pub struct Lookup {
+    pub(crate) intended: String,
+    /// Whether the answer came from one of the search domains rather than
+    /// the name as written.
+    pub(crate) via_search: bool,
+    pub(crate) expansions: u64,
+    pub(crate) wasted: Duration,
+    /// The expansions in an internal zone when the lookup failed as a whole,
+    /// the name meant may be one of them rather than the name as written.
+    pub(crate) failed_internal: Vec<String>,
+}
+
+impl Lookup {
+    fn new(intended: &str, via_search: bool) -> Self {
+        Self {
+            intended: intended.to_string(),
+            via_search,
+            expansions: 0,
+            wasted: Duration::ZERO,
+            failed_internal: Vec::new(),
+        }
+    }
+
+    /// Counts an expansion of the lookup, and the expansions collapsed into
+    /// it already.
+    fn absorb(&mut self, expansion: &Pending, failed: bool) {
+        self.expansions += 1;
+        self.wasted += expansion.held.event.duration;
+        if failed && expansion.internal {
+            self.failed_internal
+                .push(expansion.name().to_ascii_lowercase());
+        }
+        if let Some(nested) = &expansion.held.lookup {
+            self.expansions += nested.expansions;
+            self.wasted += nested.wasted;
+            if failed {
+                self.failed_internal
+                    .extend(nested.failed_internal.iter().cloned());
+            }
+        }
+    }
+}
+
+/// Held queries by qtype and lowercase name.
+type Index = HashMap<(String, String), Vec<u64>>;
+
+/// The queries held for one client.
+///
+/// Both indexes may point at queries that are no longer held, they are
+/// skipped on lookup and pruned when queries are released.
+#[derive(Default)]
+struct Queue {
+    /// Held queries in arrival order.
+    held: BTreeMap<u64, Pending>,
+    next: u64,
+    /// NXDOMAINs by each name they may be an expansion of.
+    expansions: Index,
+    /// Queries by each name they may have been written as.
+    written: Index,
+}
+
+impl Queue {
+    /// Holds `query`, returning the oldest held query when there are too
+    /// many.
+    fn push(&mut self, query: Pending) -> Option<Held> {
+        let id = self.next;
+        self.next += 1;
+        let qtype = &query.held.event.qtype;
+        for (intended, _) in &query.written_as {
+            self.written
+                .entry((qtype.clone(), intended.clone()))
+                .or_default()
+                .push(id);
+        }
+        if query.is_nxdomain() {
+            let name = query.name().to_ascii_lowercase();
+            for (end, _) in name.match_indices('.').filter(|(end, _)| *end > 0) {
+                self.expansions
+                    .entry((qtype.clone(), name[..end].to_string()))
+                    .or_default()
+                    .push(id);
+            }
+        }
+        self.held.insert(id, query);
+
+        if self.held.len() > MAX_HELD {
+            return self.held.pop_first().map(|(_, pending)| pending.held);
+        }
+        None
+    }
+
+    /// Takes the queries whose lookup is complete at `now` off the queue.
+    fn release(&mut self, now: DateTime<Utc>) -> Vec<Held> {
+        let (expired, kept): (BTreeMap<_, _>, _) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|(_, pending)| pending.expires() <= now);
+        self.held = kept;
+        for index in [&mut self.expansions, &mut self.written] {
+            index.retain(|_, ids| {
+                ids.retain(|id| self.held.contains_key(id));
+                !ids.is_empty()
+            });
+        }
+        expired.into_values().map(|pending| pending.held).collect()
+    }
+
+    /// Adds a late NXDOMAIN to the held query it is an expansion of, if any.
+    fn fold(&mut self, expansion: &Pending) -> bool {
+        let qtype = &expansion.held.event.qtype;
+        let name = expansion.name().to_ascii_lowercase();
+        // The latest query in the window for each name it may expand
+        let latest = name
+            .match_indices('.')
+            .filter_map(|(end, _)| self.written.get(&(qtype.clone(), name[..end].to_string())))
+            .filter_map(|ids| {
+                ids.iter().rev().copied().find(|id| {
+                    self.held.get(id).is_some_and(|query| {
+                        (expansion.at - query.at).abs() <= SEARCH_WINDOW
+                            && query.intended(&name).is_some()
+                    })
+                })
+            })
+            .max();
+        let Some(query) = latest.and_then(|id| self.held.get_mut(&id)) else {
+            return false;
+        };
+
+        let Some((intended, via_search)) = query.intended(&name).cloned() else {
+            return false;
+        };
+        let failed = query.held.event.rcode != "NOERROR";
+        query
+            .held
+            .lookup
+            .get_or_insert_with(|| Lookup::new(&intended, via_search))
+            .absorb(expansion, failed);
+        query.written_as = vec![(intended, via_search)];
+        true
+    }
+
+    /// Removes the expansions of `intended` queried around `at` from the
+    /// queue, into the lookup of a query that `failed` or not.
+    fn collapse(
+        &mut self,
+        qtype: &str,
+        (intended, via_search): (&str, bool),
+        at: DateTime<Utc>,
+        failed: bool,
+    ) -> Option<Lookup> {
+        let ids = self
+            .expansions
+            .get_mut(&(qtype.to_string(), intended.to_string()))?;
+        let mut lookup = Lookup::new(intended, via_search);
+        ids.retain(|id| {
+            let Some(expansion) = self.held.get(id) else {
+                return false;
+            };
+            if (at - expansion.at).abs() > SEARCH_WINDOW {
+                return true;
+            }
+            let expansion = self.held.remove(id).unwrap();
+            lookup.absorb(&expansion, failed);
+            false
+        });
+        (lookup.expansions > 0).then_some(lookup)
+    }
+}
+
+/// Groups the queries a resolver sends while walking its search list
+/// (`ndots:5` by default in Kubernetes) into the lookup the pod meant.
+///
+/// Time is the time of the queries rather than of their arrival, so replayed
+/// input collapses the same way as live input, and the queries of a lookup
+/// may arrive in any order, as they do when several replicas answer them.
+pub struct SearchPathTracker {
+    internal_zones: Vec<String>,
+    /// Held queries per client address.
+    pending: HashMap<IpAddr, Queue>,
+    /// Latest query time seen.
+    watermark: DateTime<Utc>,
+    last_sweep: DateTime<Utc>,
+}
+
+impl SearchPathTracker {
+    pub fn new(internal_zones: Vec<String>) -> Self {
+        Self {
+            internal_zones,
+            pending: HashMap::new(),
+            watermark: DateTime::<Utc>::MIN_UTC,
+            last_sweep: DateTime::<Utc>::MIN_UTC,
+        }
+    }
+
+    /// Feeds a query to the tracker, which holds it back until its lookup is
+    /// complete. Returns the queries released meanwhile, with the lookup they
+    /// concluded: NXDOMAINs that were not expansions of anything after
+    /// `SEARCH_WINDOW`, and answers after `ANSWER_HOLD`.
+    pub fn observe(&mut self, replica: &Arc<str>, event: DnsQueryEvent) -> Vec<Held> {
+        let at = event.timestamp.unwrap_or_else(Utc::now);
+        let mut released = self.advance(at);
+
+        let name = event.name.trim_end_matches('.');
+        // A failed search domain only concludes the lookup when it was the
+        // name as written, the resolver moves on otherwise.
+        let nxdomain = event.rcode == "NXDOMAIN";
+        let mut written_as: Vec<(String, bool)> = self
+            .internal_zones
+            .iter()
+            .filter(|_| !nxdomain)
+            .flat_map(|zone| search_candidates(name, zone))
+            .chain(std::iter::once((name, false)))
+            .map(|(intended, via_search)| (intended.to_ascii_lowercase(), via_search))
+            .collect();
+        let internal = self
+            .internal_zones
+            .iter()
+            .any(|zone| strip_label_suffix(name, zone).is_some());
+        let failed = event.rcode != "NOERROR";
+        let queue = self.pending.entry(event.client_ip).or_default();
+        let lookup = written_as.iter().find_map(|(intended, via_search)| {
+            queue.collapse(&event.qtype, (intended, *via_search), at, failed)
+        });
+        if let Some(lookup) = &lookup {
+            written_as = vec![(lookup.intended.clone(), lookup.via_search)];
+        }
+
+        let query = Pending {
+            held: Held {
+                replica: replica.clone(),
+                event,
+                lookup,
+            },
+            at,
+            written_as,
+            internal,
+        };
+        // An expansion that shows up after the query that concluded it
+        if nxdomain && queue.fold(&query) {
+            return released;
+        }
+        released.extend(queue.push(query));
+        released
+    }
+
+    /// Moves the time of the tracker forward, returning the queries whose
+    /// lookup is complete by then. Live input calls it periodically so that
+    /// queries do not wait for the next one.
+    pub fn advance(&mut self, now: DateTime<Utc>) -> Vec<Held> {
+        self.watermark = self.watermark.max(now);
+        let mut released = Vec::new();
+        if self.watermark - self.last_sweep < TimeDelta::seconds(1) {
+            return released;
+        }
+        self.last_sweep = self.watermark;
+        let watermark = self.watermark;
+        self.pending.retain(|_, queue| {
+            released.extend(queue.release(watermark));
+            !queue.held.is_empty()
+        });
+        released
+    }
+
+    /// Releases every held query, at the end of the input.
+    pub fn flush(&mut self) -> Vec<Held> {
+        self.pending
+            .drain()
+            .flat_map(|(_, queue)| queue.held.into_values())
+            .map(|pending| pending.held)
+            .collect()
+    }
+}
+
+/// Names a query under `zone` may have been written as, when it was
+/// answered from the `<zone>` or `svc.<zone>` search domain.
+fn search_candidates<'a>(name: &'a str, zone: &'a str) -> impl Iterator<Item = (&'a str, bool)> {
+    let in_zone = strip_label_suffix(name, zone);
+    let in_svc = in_zone.and_then(|rest| strip_label_suffix(rest, "svc"));
+    [in_zone, in_svc]
+        .into_iter()
+        .flatten()
+        .map(|intended| (intended, true))
+}
+
+/// `name` without `.<suffix>`, ignoring ASCII case.
+fn strip_label_suffix<'a>(name: &'a str, suffix: &str) -> Option<&'a str> {
+    let start = name.len().checked_sub(suffix.len() + 1)?;
+    (name.as_bytes()[start] == b'.' && name[start + 1..].eq_ignore_ascii_case(suffix))
+        .then(|| &name[..start])
+        .filter(|intended| !intended.is_empty())
+}
+
+/// Whether `name` is `intended` with more labels appended.
+fn is_expansion(name: &str, intended: &str) -> bool {
+    name.len() > intended.len()
+        && name.as_bytes()[intended.len()] == b'.'
+        && name[..intended.len()].eq_ignore_ascii_case(intended)
+}
+
+/// Cost of the search path for one client.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SearchPathCost {
+    /// Lookups that went through at least one search domain first.
+    pub(crate) lookups: u64,
+    pub(crate) wasted_queries: u64,
+    pub(crate) added_latency_secs: f64,
+    /// Wasted queries per intended name.
+    pub(crate) names: HashMap<String, u64>,
+    pub(crate) recommended_dns_config: Option<PodDNSConfig>,
+    /// Most dots in a name that only resolves through the search list.
+    #[serde(skip)]
+    search_dots: Option<usize>,
+}
+
+impl SearchPathCost {
+    pub fn observe(&mut self, lookup: &Lookup) {
+        self.lookups += 1;
+        self.wasted_queries += lookup.expansions;
+        self.added_latency_secs += lookup.wasted.as_secs_f64();
+        if self.names.len() < MAX_NAMES || self.names.contains_key(&lookup.intended) {
+            *self.names.entry(lookup.intended.clone()).or_default() += lookup.expansions;
+        }
+
+        let dots = lookup.intended.matches('.').count();
+        if lookup.via_search {
+            self.search_dots = self.search_dots.max(Some(dots));
+        }
+        self.recommended_dns_config = Some(recommended_dns_config(self.search_dots));
+    }
+
+    pub fn merge(&mut self, other: &SearchPathCost) {
+        self.lookups += other.lookups;
+        self.wasted_queries += other.wasted_queries;
+        self.added_latency_secs += other.added_latency_secs;
+        for (name, wasted) in &other.names {
+            if self.names.len() < MAX_NAMES || self.names.contains_key(name) {
+                *self.names.entry(name.clone()).or_default() += wasted;
+            }
+        }
+        self.search_dots = self.search_dots.max(other.search_dots);
+        if self.lookups > 0 {
+            self.recommended_dns_config = Some(recommended_dns_config(self.search_dots));
+        }
+    }
+}
+
+/// The lowest `ndots` that still resolves the short names the client relies
+/// on through the search list. Names with at least that many dots are tried
+/// as written first.
+fn recommended_dns_config(search_dots: Option<usize>) -> PodDNSConfig {
+    let ndots = search_dots.map_or(1, |dots| dots + 1);
+    PodDNSConfig {
+        options: Some(vec![PodDNSConfigOption {
+            name: Some("ndots".to_string()),
+            value: Some(ndots.to_string()),
+        }]),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "2025-01-01T10:00:00Z";
+
+    fn query(name: &str, rcode: &str, millis: i64) -> DnsQueryEvent {
+        let start: DateTime<Utc> = START.parse().unwrap();
+        DnsQueryEvent {
+            client_ip: "10.244.0.5".parse().unwrap(),
+            client_port: 43211,
+            id: 1,
+            qtype: "A".to_string(),
+            qclass: "IN".to_string(),
+            name: format!("{}.", name),
+            proto: "udp".to_string(),
+            size: 40,
+            do_bit: false,
+            bufsize: 1232,
+            rcode: rcode.to_string(),
+            rflags: Vec::new(),
+            rsize: 40,
+            duration: Duration::from_millis(1),
+            answers: None,
+            timestamp: Some(start + TimeDelta::milliseconds(millis)),
+        }
+    }
+
+    fn tracker() -> SearchPathTracker {
+        SearchPathTracker::new(vec!["cluster.local".to_string()])
+    }
+
+    fn replica() -> Arc<str> {
+        Arc::from("coredns-1")
+    }
+
+    /// Feeds the queries and returns everything released, flushed at the end.
+    fn run(tracker: &mut SearchPathTracker, queries: &[DnsQueryEvent]) -> Vec<Held> {
+        let mut released: Vec<Held> = queries
+            .iter()
+            .flat_map(|query| tracker.observe(&replica(), query.clone()))
+            .collect();
+        released.extend(tracker.flush());
+        released
+    }
+
+    fn expansions() -> Vec<DnsQueryEvent> {
+        vec![
+            query("api.stripe.com.shop.svc.cluster.local", "NXDOMAIN", 0),
+            query("api.stripe.com.svc.cluster.local", "NXDOMAIN", 1),
+            query("api.stripe.com.cluster.local", "NXDOMAIN", 2),
+        ]
+    }
+
+    fn assert_collapsed(released: &[Held]) {
+        assert_eq!(released.len(), 1);
+        let lookup = released[0].lookup.as_ref().unwrap();
+        assert_eq!(released[0].event.rcode, "NOERROR");
+        assert_eq!(lookup.intended, "api.stripe.com");
+        assert!(!lookup.via_search);
+        assert_eq!(lookup.expansions, 3);
+        assert_eq!(lookup.wasted, Duration::from_millis(3));
+    }
+
+    #[test]
+    fn collapses_expansions_into_the_answer() {
+        let mut queries = expansions();
+        queries.push(query("api.stripe.com", "NOERROR", 3));
+        assert_collapsed(&run(&mut tracker(), &queries));
+    }
+
+    #[test]
+    fn collapses_expansions_arriving_after_the_answer() {
+        let mut queries = vec![query("api.stripe.com", "NOERROR", 3)];
+        queries.extend(expansions());
+        assert_collapsed(&run(&mut tracker(), &queries));
+
+        let mut queries = expansions();
+        queries.insert(1, query("api.stripe.com", "NOERROR", 3));
+        assert_collapsed(&run(&mut tracker(), &queries));
+    }
+
+    #[test]
+    fn matches_names_ignoring_case() {
+        // Resolvers randomizing the case of query names (0x20)
+        let mut queries = vec![
+            query("Api.Stripe.com.shop.svc.cluster.local", "NXDOMAIN", 0),
+            query("api.stripe.COM.svc.cluster.local", "NXDOMAIN", 1),
+        ];
+        queries.push(query("API.stripe.com.Cluster.Local", "NXDOMAIN", 2));
+        queries.push(query("api.Stripe.com", "NOERROR", 3));
+        let released = run(&mut tracker(), &queries);
+        assert_eq!(released.len(), 1);
+        let lookup = released[0].lookup.as_ref().unwrap();
+        assert_eq!(lookup.intended, "api.stripe.com");
+        assert_eq!(lookup.expansions, 3);
+    }
+
+    #[test]
+    fn finds_names_resolved_through_the_search_list() {
+        let queries = [
+            query("web.shop.svc.cluster.local", "NOERROR", 1),
+            query("web.shop.shop.svc.cluster.local", "NXDOMAIN", 0),
+        ];
+        let released = run(&mut tracker(), &queries);
+        assert_eq!(released.len(), 1);
+        let lookup = released[0].lookup.as_ref().unwrap();
+        assert_eq!(lookup.intended, "web.shop");
+        assert!(lookup.via_search);
+        assert_eq!(lookup.expansions, 1);
+    }
+
+    #[test]
+    fn uses_the_time_of_the_queries() {
+        // Replayed input arrives all at once, the queries are minutes apart
+        let queries = [
+            query("api.stripe.com.cluster.local", "NXDOMAIN", 0),
+            query("api.stripe.com", "NOERROR", 60_000),
+        ];
+        let released = run(&mut tracker(), &queries);
+        assert_eq!(released.len(), 2);
+        assert!(released.iter().all(|held| held.lookup.is_none()));
+    }
+
+    #[test]
+    fn releases_queries_once_their_lookup_is_complete() {
+        let mut tracker = tracker();
+        assert!(
+            tracker
+                .observe(&replica(), query("missing.example.com", "NXDOMAIN", 0))
+                .is_empty()
+        );
+        assert!(
+            tracker
+                .observe(&replica(), query("api.stripe.com", "NOERROR", 1_000))
+                .is_empty()
+        );
+
+        let released = tracker.advance(START.parse::<DateTime<Utc>>().unwrap() + ANSWER_HOLD * 2);
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].event.name, "api.stripe.com.");
+
+        let released = tracker.advance(START.parse::<DateTime<Utc>>().unwrap() + SEARCH_WINDOW);
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].event.rcode, "NXDOMAIN");
+        assert!(released[0].lookup.is_none());
+        assert!(tracker.flush().is_empty());
+    }
+
+    #[test]
+    fn keeps_up_with_a_burst_of_failed_lookups() {
+        // A pod hammering a missing Service, 4000 queries a second
+        let mut tracker = tracker();
+        let mut released = Vec::new();
+        for lookup in 0..5_000 {
+            for name in [
+                "missing.shop.svc.cluster.local",
+                "missing.svc.cluster.local",
+                "missing.cluster.local",
+                "missing",
+            ] {
+                released.extend(tracker.observe(&replica(), query(name, "NXDOMAIN", lookup)));
+            }
+            assert!(
+                tracker
+                    .pending
+                    .values()
+                    .all(|queue| queue.held.len() <= MAX_HELD)
+            );
+        }
+        released.extend(tracker.flush());
+
+        assert_eq!(released.len(), 5_000);
+        assert!(released.iter().all(|held| held.event.name == "missing."));
+        let expansions: u64 = released
+            .iter()
+            .filter_map(|held| held.lookup.as_ref())
+            .map(|lookup| lookup.expansions)
+            .sum();
+        assert_eq!(expansions, 15_000);
+    }
+
+    #[test]
+    fn keeps_internal_expansions_of_failed_lookups() {
+        let mut queries = vec![query("web-api", "NXDOMAIN", 3)];
+        queries.extend([
+            query("web-api.shop.svc.cluster.local", "NXDOMAIN", 0),
+            query("web-api.svc.cluster.local", "NXDOMAIN", 1),
+            query("web-api.example.internal", "NXDOMAIN", 2),
+        ]);
+        let released = run(&mut tracker(), &queries);
+        assert_eq!(released.len(), 1);
+        let lookup = released[0].lookup.as_ref().unwrap();
+        assert_eq!(lookup.intended, "web-api");
+        assert_eq!(lookup.expansions, 3);
+        assert_eq!(
+            lookup.failed_internal,
+            [
+                "web-api.shop.svc.cluster.local",
+                "web-api.svc.cluster.local"
+            ]
+        );
+
+        // Nothing to keep when the lookup succeeds
+        let mut queries = expansions();
+        queries.push(query("api.stripe.com", "NOERROR", 3));
+        let released = run(&mut tracker(), &queries);
+        assert!(
+            released[0]
+                .lookup
+                .as_ref()
+                .unwrap()
+                .failed_internal
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Shared with build.rs, which lays out the tables that are compiled in.
+
+/// Set of domain names stored as an open addressing hash table, so a lookup
+/// hashes the name in place, ignoring ASCII case, without allocating.
+#[derive(Clone, Copy)]
+pub struct SuffixSet {
+    /// Power of two number of slots, empty strings are free slots.
+    slots: &'static [&'static str],
+}
+
+impl SuffixSet {
+    pub const fn new(slots: &'static [&'static str]) -> Self {
+        Self { slots }
+    }
+
+    /// Builds a set at runtime. The names live for the rest of the process.
+    pub fn from_names(names: Vec<String>) -> Self {
+        let names: &'static [String] = names.leak();
+        Self::new(layout(names).leak())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        let mask = self.slots.len() - 1;
+        let mut index = hash(name) & mask;
+        loop {
+            match self.slots[index] {
+                "" => return false,
+                slot if slot.eq_ignore_ascii_case(name) => return true,
+                _ => index = (index + 1) & mask,
+            }
+        }
+    }
+}
+
+/// FNV-1a over the lowercased bytes.
+fn hash(name: &str) -> usize {
+    name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte.to_ascii_lowercase() as u64).wrapping_mul(0x100000001b3)
+    }) as usize
+}
+
+/// Places lowercase names in the slots `SuffixSet::contains` probes.
+pub fn layout(names: &[String]) -> Vec<&str> {
+    let mut slots = vec![""; (names.len() * 2).next_power_of_two().max(2)];
+    let mask = slots.len() - 1;
+    for name in names {
+        let mut index = hash(name) & mask;
+        while !slots[index].is_empty() && slots[index] != name {
+            index = (index + 1) & mask;
+        }
+        slots[index] = name;
+    }
+    slots
+}
+
+/// Parses the IANA `tlds-alpha-by-domain.txt` format: one TLD per line,
+/// `#` comments.
+pub fn parse_tlds(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|tld| tld.to_ascii_lowercase())
+        .collect()
+}
+
+/// Public Suffix List rules, split by kind.
+#[derive(Default)]
+pub struct PslRules {
+    pub rules: Vec<String>,
+    /// `*.<parent>` rules, holding the parent.
+    pub wildcards: Vec<String>,
+    /// `!<name>` rules, holding the name.
+    pub exceptions: Vec<String>,
+}
+
+/// Parses `public_suffix_list.dat`, both the ICANN and private sections.
+/// Query names are ASCII, so the unicode forms of IDN rules are skipped.
+pub fn parse_psl(content: &str) -> PslRules {
+    let mut psl = PslRules::default();
+    let rules = content
+        .lines()
+        .filter(|line| !line.starts_with("//"))
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|rule| rule.is_ascii())
+        .map(|rule| rule.to_ascii_lowercase());
+    for rule in rules {
+        if let Some(parent) = rule.strip_prefix("*.") {
+            psl.wildcards.push(parent.to_string());
+        } else if let Some(name) = rule.strip_prefix('!') {
+            psl.exceptions.push(name.to_string());
+        } else {
+            psl.rules.push(rule);
+        }
+    }
+    psl
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(names: &[&str]) -> SuffixSet {
+        SuffixSet::from_names(names.iter().map(|name| name.to_string()).collect())
+    }
+
+    #[test]
+    fn contains_names_ignoring_case() {
+        let names: Vec<String> = (0..100).map(|i| format!("name{}.example", i)).collect();
+        let set = SuffixSet::from_names(names.clone());
+        for name in &names {
+            assert!(set.contains(name));
+            assert!(set.contains(&name.to_ascii_uppercase()));
+        }
+        assert!(!set.contains("name100.example"));
+        assert!(!set.contains("example"));
+    }
+
+    #[test]
+    fn empty_set_contains_nothing() {
+        assert!(!set(&[]).contains("com"));
+        assert!(!set(&[]).contains(""));
+    }
+
+    #[test]
+    fn lays_out_duplicates_once() {
+        let names = vec!["com".to_string(), "com".to_string(), "net".to_string()];
+        let slots = layout(&names);
+        assert_eq!(slots.iter().filter(|slot| **slot == "com").count(), 1);
+        assert!(slots.len().is_power_of_two());
+        assert!(set(&["com", "com", "net"]).contains("net"));
+    }
+
+    #[test]
+    fn parses_tlds() {
+        let tlds = parse_tlds("# Version 2025010100\nCOM\n\nXN--P1AI\n");
+        assert_eq!(tlds, ["com", "xn--p1ai"]);
+    }
+
+    #[test]
+    fn parses_psl_rules_by_kind() {
+        let psl = parse_psl(
+            "// ===BEGIN ICANN DOMAINS===\n\
+             co.uk\n\
+             *.ck\n\
+             !www.ck\n\
+             香港\n\
+             S3.amazonaws.com  // trailing comment\n",
+        );
+        assert_eq!(psl.rules, ["co.uk", "s3.amazonaws.com"]);
+        assert_eq!(psl.wildcards, ["ck"]);
+        assert_eq!(psl.exceptions, ["www.ck"]);
+    }
+}
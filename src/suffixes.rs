@@ -0,0 +1,63 @@
+//! TLDs and public suffixes, compiled in from `data/` by build.rs or loaded
+//! at startup with `--tld-file` and `--psl-file`.
+
+use anyhow::{Context, Result};
+use std::sync::OnceLock;
+
+use crate::suffix_set::{self, SuffixSet};
+
+mod builtin {
+    use crate::suffix_set::SuffixSet;
+
+    include!(concat!(env!("OUT_DIR"), "/suffix_sets.rs"));
+}
+
+static TLDS: OnceLock<SuffixSet> = OnceLock::new();
+static PUBLIC_SUFFIXES: OnceLock<PublicSuffixes> = OnceLock::new();
+
+/// The Public Suffix List as `SuffixSet`s, see `suffix_set::PslRules`.
+pub struct PublicSuffixes {
+    pub(crate) rules: SuffixSet,
+    pub(crate) wildcards: SuffixSet,
+    pub(crate) exceptions: SuffixSet,
+}
+
+/// Replaces the compiled-in lists with the given files. Must be called
+/// before the first lookup.
+pub fn load(tld_file: Option<&str>, psl_file: Option<&str>) -> Result<()> {
+    if let Some(path) = tld_file {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read TLD file {}", path))?;
+        let tlds = suffix_set::parse_tlds(&content);
+        log::info!("Loaded {} TLDs from {}", tlds.len(), path);
+        let _ = TLDS.set(SuffixSet::from_names(tlds));
+    }
+    if let Some(path) = psl_file {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read Public Suffix List {}", path))?;
+        let psl = suffix_set::parse_psl(&content);
+        log::info!(
+            "Loaded {} public suffix rules from {}",
+            psl.rules.len() + psl.wildcards.len() + psl.exceptions.len(),
+            path
+        );
+        let _ = PUBLIC_SUFFIXES.set(PublicSuffixes {
+            rules: SuffixSet::from_names(psl.rules),
+            wildcards: SuffixSet::from_names(psl.wildcards),
+            exceptions: SuffixSet::from_names(psl.exceptions),
+        });
+    }
+    Ok(())
+}
+
+pub fn tlds() -> &'static SuffixSet {
+    TLDS.get_or_init(|| builtin::TLDS)
+}
+
+pub fn public_suffixes() -> &'static PublicSuffixes {
+    PUBLIC_SUFFIXES.get_or_init(|| PublicSuffixes {
+        rules: builtin::PSL_RULES,
+        wildcards: builtin::PSL_WILDCARDS,
+        exceptions: builtin::PSL_EXCEPTIONS,
+    })
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     time::{Duration, Instant},
 };
 
@@ -11,7 +11,7 @@ use crossterm::{
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use ratatui::widgets::canvas::{Canvas, Circle, Line as CanvasLine, Points, Rectangle};
+use ratatui::widgets::canvas::{Canvas, Circle, Line as CanvasLine, Points};
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
@@ -20,34 +20,11 @@ use ratatui::{
     text::{Line, Span},
     widgets::{Block, Borders, Clear, Paragraph, Tabs, Wrap},
 };
-use serde::Serialize;
-
-use crate::log_analyzer::DnsData;
-
-pub(crate) async fn test() -> Result<()> {
-    // Demo data if you want to run without hooking into your crate yet
-    let demo = DnsData {
-        internal: HashMap::from([
-            ("pod-a".into(), vec!["svc-auth".into(), "svc-api".into()]),
-            ("pod-b".into(), vec!["svc-api".into(), "svc-db".into()]),
-            ("pod-c".into(), vec!["svc-queue".into()]),
-        ]),
-        external: HashMap::from([
-            (
-                "pod-a".into(),
-                vec!["api.stripe.com".into(), "cdn.example.com".into()],
-            ),
-            ("pod-b".into(), vec!["registry-1.docker.io".into()]),
-            (
-                "pod-c".into(),
-                vec!["charts.helm.sh".into(), "k8s.gcr.io".into()],
-            ),
-        ]),
-    };
 
-    // In your integration, replace `demo` with a channel that receives DnsData snapshots
-    // and call `app.update_data(new_data)` when fresh logs are parsed.
+use crate::config::{CONFIG, Granularity};
+use crate::log_analyzer::{AaaaWasteEmpty, AaaaWasteReport, ClientErrors, DnsData, LogAnalyzer};
 
+pub(crate) async fn run(mut analyzer: LogAnalyzer) -> Result<()> {
     // TUI setup
     enable_raw_mode()?;
     let mut stdout = std::io::stdout();
@@ -56,22 +33,24 @@ pub(crate) async fn test() -> Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     let mut app = AppState::default();
-    app.last_tick = Instant::now();
-    app.update_data(demo);
 
     let tick_rate = Duration::from_millis(16); // ~60 FPS animations
     let mut running = true;
 
     while running {
+        if let Some(data) = analyzer.try_update() {
+            app.update_data(data);
+        }
+
         // Draw
         terminal.draw(|f| ui(f, &mut app))?;
 
         // Poll input with a tiny timeout so we keep animating
         let timeout = tick_rate.saturating_sub(app.last_tick.elapsed());
-        if event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                running = handle_key(key, &mut app)?;
-            }
+        if event::poll(timeout)?
+            && let Event::Key(key) = event::read()?
+        {
+            running = handle_key(key, &mut app)?;
         }
         animate(&mut app);
     }
@@ -92,6 +71,10 @@ enum NodeKind {
     External,
     Pod,
     Service,
+    /// Address asked about in a PTR lookup.
+    Reverse,
+    /// A node querying from its own address, drawn with the pods.
+    Node,
 }
 
 #[derive(Clone, Debug)]
@@ -121,9 +104,17 @@ struct Filters {
 
 #[derive(Clone, Debug)]
 struct AppState {
+    /// Latest update, as recorded.
+    recorded: DnsData,
+    /// The update grouped at the selected granularity.
     data: DnsData,
+    granularity: Granularity,
     nodes: HashMap<String, Node>,
     edges: Vec<Edge>,
+    /// Failed queries per client, computed with each update.
+    errors: Vec<ClientErrors>,
+    /// AAAA queries that stay empty, computed with each update.
+    aaaa_waste: AaaaWasteReport,
     filters: Filters,
     tab: usize,
     last_tick: Instant,
@@ -134,9 +125,13 @@ struct AppState {
 impl Default for AppState {
     fn default() -> Self {
         Self {
+            recorded: Default::default(),
             data: Default::default(),
+            granularity: CONFIG.granularity,
             nodes: Default::default(),
             edges: Default::default(),
+            errors: Default::default(),
+            aaaa_waste: Default::default(),
             filters: Default::default(),
             tab: Default::default(),
             last_tick: Instant::now(),
@@ -162,6 +157,10 @@ fn handle_key(key: KeyEvent, app: &mut AppState) -> Result<bool> {
             (KeyCode::Char('q'), _) | (KeyCode::Esc, _) => return Ok(false),
             (KeyCode::Char('1'), _) => app.tab = 0, // Graph
             (KeyCode::Char('2'), _) => app.tab = 1, // Lists
+            (KeyCode::Char('3'), _) => app.tab = 2, // Search path
+            (KeyCode::Char('4'), _) => app.tab = 3, // Errors
+            (KeyCode::Char('5'), _) => app.tab = 4, // Dangling
+            (KeyCode::Char('6'), _) => app.tab = 5, // AAAA waste
             (KeyCode::Char('/'), _) => {
                 app.input_mode = InputMode::FilterPod;
                 app.input_buffer.clear();
@@ -177,6 +176,10 @@ fn handle_key(key: KeyEvent, app: &mut AppState) -> Result<bool> {
             (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
                 app.input_mode = InputMode::ClearConfirm;
             }
+            (KeyCode::Char('g'), _) => {
+                app.granularity = app.granularity.next();
+                app.update_data(app.recorded.clone());
+            }
             _ => {}
         },
         InputMode::FilterPod
@@ -231,75 +234,122 @@ fn non_empty(s: &str) -> Option<String> {
 
 impl AppState {
     fn update_data(&mut self, data: DnsData) {
-        self.data = data;
+        self.data = data.aggregate(self.granularity);
+        self.recorded = data;
+        self.errors = self.data.errors();
+        self.aaaa_waste = self.data.aaaa_waste();
         self.rebuild_graph();
         self.recompute_targets();
     }
 
     fn rebuild_graph(&mut self) {
-        self.nodes.clear();
+        let previous = std::mem::take(&mut self.nodes);
         self.edges.clear();
         // Create nodes
+        let nodes = &self.data.nodes;
+        let client_kind = |client: &str| {
+            if nodes.contains(client) {
+                NodeKind::Node
+            } else {
+                NodeKind::Pod
+            }
+        };
         let mut seen = HashSet::new();
-        for (pod, svcs) in &self.data.internal {
-            seen.insert(pod.clone());
-            self.nodes.entry(pod.clone()).or_insert(Node {
-                id: pod.clone(),
-                kind: NodeKind::Pod,
-                x: 0.0,
-                y: 0.0,
-                tx: 0.0,
-                ty: 0.0,
-            });
-            for s in svcs {
-                if seen.insert(s.clone()) {
-                    self.nodes.entry(s.clone()).or_insert(Node {
-                        id: s.clone(),
-                        kind: NodeKind::Service,
-                        x: 0.0,
-                        y: 0.0,
-                        tx: 0.0,
-                        ty: 0.0,
-                    });
-                }
+        for (name, pods) in &self.data.internal {
+            // Pod and headless member records that resolved to a pod are
+            // drawn as pod-to-pod edges.
+            let (s, kind) = match name.target_pod() {
+                Some(pod) => (pod.to_string(), client_kind(pod)),
+                None => (name.to_string(), NodeKind::Service),
+            };
+            if seen.insert(s.clone()) {
+                self.nodes.entry(s.clone()).or_insert(Node {
+                    id: s.clone(),
+                    kind,
+                    x: 0.0,
+                    y: 0.0,
+                    tx: 0.0,
+                    ty: 0.0,
+                });
+            }
+            for pod in pods.keys() {
+                seen.insert(pod.clone());
+                self.nodes.entry(pod.clone()).or_insert(Node {
+                    id: pod.clone(),
+                    kind: client_kind(pod),
+                    x: 0.0,
+                    y: 0.0,
+                    tx: 0.0,
+                    ty: 0.0,
+                });
                 self.edges.push(Edge {
                     from: pod.clone(),
                     to: s.clone(),
                 });
             }
         }
-        for (pod, exts) in &self.data.external {
-            self.nodes.entry(pod.clone()).or_insert(Node {
-                id: pod.clone(),
-                kind: NodeKind::Pod,
+        for (d, pods) in &self.data.external {
+            if seen.insert(d.clone()) {
+                self.nodes.entry(d.clone()).or_insert(Node {
+                    id: d.clone(),
+                    kind: NodeKind::External,
+                    x: 0.0,
+                    y: 0.0,
+                    tx: 0.0,
+                    ty: 0.0,
+                });
+            }
+            for pod in pods.keys() {
+                self.nodes.entry(pod.clone()).or_insert(Node {
+                    id: pod.clone(),
+                    kind: client_kind(pod),
+                    x: 0.0,
+                    y: 0.0,
+                    tx: 0.0,
+                    ty: 0.0,
+                });
+                self.edges.push(Edge {
+                    from: d.clone(),
+                    to: pod.clone(),
+                }); // external -> pod (outer to middle)
+            }
+        }
+        for (name, pods) in &self.data.reverse {
+            let s = name.to_string();
+            self.nodes.entry(s.clone()).or_insert(Node {
+                id: s.clone(),
+                kind: NodeKind::Reverse,
                 x: 0.0,
                 y: 0.0,
                 tx: 0.0,
                 ty: 0.0,
             });
-            for d in exts {
-                if seen.insert(d.clone()) {
-                    self.nodes.entry(d.clone()).or_insert(Node {
-                        id: d.clone(),
-                        kind: NodeKind::External,
-                        x: 0.0,
-                        y: 0.0,
-                        tx: 0.0,
-                        ty: 0.0,
-                    });
-                }
+            for pod in pods.keys() {
+                self.nodes.entry(pod.clone()).or_insert(Node {
+                    id: pod.clone(),
+                    kind: client_kind(pod),
+                    x: 0.0,
+                    y: 0.0,
+                    tx: 0.0,
+                    ty: 0.0,
+                });
                 self.edges.push(Edge {
-                    from: d.clone(),
-                    to: pod.clone(),
-                }); // external -> pod (outer to middle)
+                    from: pod.clone(),
+                    to: s.clone(),
+                }); // pod -> reverse (middle to inner)
             }
         }
-        // initialize sprinkled positions to avoid popping
+        // initialize sprinkled positions to avoid popping, keep known nodes where they are
         use rand::{Rng, SeedableRng, rngs::StdRng};
         let mut rng = StdRng::seed_from_u64(42);
         for n in self.nodes.values_mut() {
-            n.x = rng.random_range(-1.0..1.0);
-            n.y = rng.random_range(-1.0..1.0);
+            if let Some(prev) = previous.get(&n.id) {
+                n.x = prev.x;
+                n.y = prev.y;
+            } else {
+                n.x = rng.random_range(-1.0..1.0);
+                n.y = rng.random_range(-1.0..1.0);
+            }
             n.tx = n.x;
             n.ty = n.y;
         }
@@ -319,13 +369,8 @@ impl AppState {
                 .data
                 .internal
                 .iter()
-                .filter_map(|(p, svcs)| {
-                    if svcs.iter().any(|s| s.contains(svc)) {
-                        Some(p.clone())
-                    } else {
-                        None
-                    }
-                })
+                .filter(|(s, _)| s.to_string().contains(svc))
+                .flat_map(|(_, pods)| pods.keys().cloned())
                 .collect();
             allowed_pods = Some(match allowed_pods {
                 Some(a) => &a & &pods,
@@ -337,13 +382,8 @@ impl AppState {
                 .data
                 .external
                 .iter()
-                .filter_map(|(p, ex)| {
-                    if ex.iter().any(|d| d.contains(ext)) {
-                        Some(p.clone())
-                    } else {
-                        None
-                    }
-                })
+                .filter(|(d, _)| d.contains(ext))
+                .flat_map(|(_, pods)| pods.keys().cloned())
                 .collect();
             allowed_pods = Some(match allowed_pods {
                 Some(a) => &a & &pods,
@@ -372,7 +412,7 @@ impl AppState {
         let pods: Vec<String> = self
             .nodes
             .values()
-            .filter(|n| matches!(n.kind, NodeKind::Pod))
+            .filter(|n| matches!(n.kind, NodeKind::Pod | NodeKind::Node))
             .filter(|n| match &allowed_pods {
                 Some(pods) => pods.contains(&n.id),
                 None => true,
@@ -383,7 +423,7 @@ impl AppState {
         let services: Vec<String> = self
             .nodes
             .values()
-            .filter(|n| matches!(n.kind, NodeKind::Service))
+            .filter(|n| matches!(n.kind, NodeKind::Service | NodeKind::Reverse))
             .filter(|n| match &allowed_pods {
                 Some(pods) => {
                     // keep only services that are pointed by allowed pods
@@ -403,7 +443,7 @@ impl AppState {
     }
 }
 
-fn place_on_circle(app: &mut AppState, ids: &Vec<String>, radius: f64) {
+fn place_on_circle(app: &mut AppState, ids: &[String], radius: f64) {
     if ids.is_empty() {
         return;
     }
@@ -443,7 +483,16 @@ fn ui(f: &mut ratatui::Frame, app: &mut AppState) {
         .split(size);
 
     // Header with tabs
-    let titles = ["Graph", "Lists"].iter().map(|t| {
+    let titles = [
+        "Graph",
+        "Lists",
+        "Search path",
+        "Errors",
+        "Dangling",
+        "AAAA",
+    ]
+    .iter()
+    .map(|t| {
         Line::from(Span::styled(
             *t,
             Style::default().fg(PASTEL_3).add_modifier(Modifier::BOLD),
@@ -458,6 +507,10 @@ fn ui(f: &mut ratatui::Frame, app: &mut AppState) {
     match app.tab {
         0 => draw_graph(f, chunks[1], app),
         1 => draw_lists(f, chunks[1], app),
+        2 => draw_search_path(f, chunks[1], app),
+        3 => draw_errors(f, chunks[1], app),
+        4 => draw_dangling(f, chunks[1], app),
+        5 => draw_aaaa_waste(f, chunks[1], app),
         _ => {}
     }
 
@@ -549,7 +602,9 @@ fn draw_graph(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
                 let (c, r) = match n.kind {
                     NodeKind::External => (PASTEL_1, 0.012),
                     NodeKind::Pod => (PASTEL_3, 0.014),
+                    NodeKind::Node => (PASTEL_7, 0.016),
                     NodeKind::Service => (PASTEL_6, 0.016),
+                    NodeKind::Reverse => (PASTEL_2, 0.012),
                 };
                 ctx.draw(&Circle {
                     x: n.x,
@@ -564,7 +619,7 @@ fn draw_graph(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
                 if !node_visible(app, n) {
                     continue;
                 }
-                if matches!(n.kind, NodeKind::Pod) {
+                if matches!(n.kind, NodeKind::Pod | NodeKind::Node) {
                     ctx.print(
                         n.x,
                         n.y,
@@ -576,7 +631,7 @@ fn draw_graph(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("Onion Graph (outer: external, middle: pods, inner: services)"),
+                .title("Onion Graph (outer: external, middle: pods, inner: services, PTR)"),
         );
     f.render_widget(canvas, area);
 }
@@ -612,7 +667,7 @@ fn node_visible(app: &AppState, n: &Node) -> bool {
     ) {
         (None, None, None) => true,
         _ => match n.kind {
-            NodeKind::Pod => {
+            NodeKind::Pod | NodeKind::Node => {
                 app.filters
                     .pod
                     .as_ref()
@@ -631,7 +686,7 @@ fn node_visible(app: &AppState, n: &Node) -> bool {
                         .map(|s| n.id.contains(s))
                         .unwrap_or(true)
             }
-            NodeKind::Service => app
+            NodeKind::Service | NodeKind::Reverse => app
                 .filters
                 .service
                 .as_ref()
@@ -673,11 +728,35 @@ fn draw_lists(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
         .filter(|n| matches!(n.kind, NodeKind::External))
         .map(|n| n.id.clone())
         .collect();
+    // queries that did not use udp, per pod and protocol
+    let mut non_udp: HashMap<&str, BTreeMap<&str, u64>> = HashMap::new();
+    let edges = app
+        .data
+        .internal
+        .values()
+        .chain(app.data.external.values())
+        .chain(app.data.reverse.values());
+    for pods in edges {
+        for (pod, stats) in pods {
+            for (proto, count) in stats.protocols.iter().filter(|(proto, _)| *proto != "udp") {
+                *non_udp.entry(pod).or_default().entry(proto).or_default() += count;
+            }
+        }
+    }
     let pods = app
         .nodes
         .values()
-        .filter(|n| matches!(n.kind, NodeKind::Pod))
-        .map(|n| n.id.clone())
+        .filter(|n| matches!(n.kind, NodeKind::Pod | NodeKind::Node))
+        .map(|n| match non_udp.get(n.id.as_str()) {
+            Some(protocols) if !protocols.is_empty() => {
+                let counts: Vec<String> = protocols
+                    .iter()
+                    .map(|(proto, count)| format!("{} {}", count, proto))
+                    .collect();
+                format!("{} ({})", n.id, counts.join(", "))
+            }
+            _ => n.id.clone(),
+        })
         .collect();
     let svcs = app
         .nodes
@@ -691,15 +770,212 @@ fn draw_lists(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
     f.render_widget(mk("Services", svcs), layout[2]);
 }
 
+fn draw_search_path(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
+    let mut costs: Vec<_> = app
+        .data
+        .search_path
+        .iter()
+        .filter(|(pod, _)| {
+            app.filters
+                .pod
+                .as_ref()
+                .map(|needle| pod.contains(needle))
+                .unwrap_or(true)
+        })
+        .collect();
+    costs.sort_by_key(|(_, cost)| std::cmp::Reverse(cost.wasted_queries));
+
+    let lines: Vec<Line> = costs
+        .into_iter()
+        .map(|(pod, cost)| {
+            let ndots = cost
+                .recommended_dns_config
+                .iter()
+                .flat_map(|config| config.options.iter().flatten())
+                .filter_map(|option| option.value.as_deref())
+                .next()
+                .unwrap_or("-");
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<40}", truncate(pod, 39)),
+                    Style::default().fg(PASTEL_3),
+                ),
+                Span::raw(format!(
+                    "{:>6} wasted in {:>6} lookups  +{:>8.1} ms  ",
+                    cost.wasted_queries,
+                    cost.lookups,
+                    cost.added_latency_secs * 1000.0
+                )),
+                Span::styled(
+                    format!("recommended ndots:{}", ndots),
+                    Style::default().fg(PASTEL_1),
+                ),
+            ])
+        })
+        .collect();
+
+    let p = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Search-path cost per pod"),
+    );
+    f.render_widget(p, area);
+}
+
+fn draw_errors(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
+    let lines: Vec<Line> = app
+        .errors
+        .iter()
+        .filter(|client| {
+            app.filters
+                .pod
+                .as_ref()
+                .map(|needle| client.client.contains(needle))
+                .unwrap_or(true)
+        })
+        .map(|client| {
+            let rcodes = client
+                .rcodes
+                .iter()
+                .map(|(rcode, count)| format!("{} {}", rcode, count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let names = client
+                .names
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<40}", truncate(&client.client, 39)),
+                    Style::default().fg(PASTEL_3),
+                ),
+                Span::styled(
+                    format!("{:>6.1}%  ", client.failure_rate * 100.0),
+                    Style::default().fg(PASTEL_5),
+                ),
+                Span::raw(format!(
+                    "{:>6}/{:<6} {:<30} ",
+                    client.failures,
+                    client.queries,
+                    truncate(&rcodes, 29)
+                )),
+                Span::styled(names, Style::default().fg(Color::DarkGray)),
+            ])
+        })
+        .collect();
+
+    let p = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Failed queries per pod, by failure rate"),
+    );
+    f.render_widget(p, area);
+}
+
+fn draw_dangling(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
+    let mut names: Vec<_> = app.data.dangling.iter().collect();
+    names.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut lines: Vec<Line> = Vec::new();
+    for (name, dependency) in names {
+        lines.push(Line::from(vec![
+            Span::styled(name.clone(), Style::default().fg(PASTEL_5)),
+            Span::styled(
+                format!("  {:?}", dependency.reason),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+        let mut pods: Vec<_> = dependency.pods.iter().collect();
+        pods.sort_by_key(|(_, sighting)| std::cmp::Reverse(sighting.last_seen));
+        for (pod, sighting) in pods {
+            lines.push(Line::from(Span::raw(format!(
+                "    {:<40} {:>6} queries  first {}  last {}",
+                truncate(pod, 39),
+                sighting.queries,
+                sighting.first_seen.format("%Y-%m-%d %H:%M:%S"),
+                sighting.last_seen.format("%Y-%m-%d %H:%M:%S"),
+            ))));
+        }
+    }
+
+    let p = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Dangling dependencies"),
+    );
+    f.render_widget(p, area);
+}
+
+fn draw_aaaa_waste(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
+    let report = &app.aaaa_waste;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("AAAA queries that never get an answer (dnstap only)");
+    if let Some(reason) = report.empty_reason {
+        let p = Paragraph::new(match reason {
+            AaaaWasteEmpty::NoAnswers => {
+                "Unavailable for log input: CoreDNS logs do not carry the answers. \
+                 Use --dnstap-listen or --dnstap-file to see AAAA queries that stay empty."
+            }
+            AaaaWasteEmpty::NoAaaaQueries => "No AAAA queries seen yet.",
+            AaaaWasteEmpty::AllAnswered => {
+                "None: every name queried for AAAA returned an AAAA record at least once."
+            }
+        })
+        .style(Style::default().fg(Color::DarkGray))
+        .wrap(Wrap { trim: true })
+        .block(block);
+        f.render_widget(p, area);
+        return;
+    }
+
+    let lines: Vec<Line> = report
+        .clients
+        .iter()
+        .filter(|client| {
+            app.filters
+                .pod
+                .as_ref()
+                .map(|needle| client.client.contains(needle))
+                .unwrap_or(true)
+        })
+        .map(|client| {
+            let names = client
+                .names
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<40}", truncate(&client.client, 39)),
+                    Style::default().fg(PASTEL_3),
+                ),
+                Span::raw(format!(
+                    "{:>6} empty AAAA  {:>6} AAAA  {:>6} A  ",
+                    client.wasted_queries, client.aaaa_queries, client.a_queries
+                )),
+                Span::styled(names, Style::default().fg(Color::DarkGray)),
+            ])
+        })
+        .collect();
+
+    let p = Paragraph::new(lines).block(block);
+    f.render_widget(p, area);
+}
+
 fn draw_footer(f: &mut ratatui::Frame, area: Rect, app: &AppState) {
     let filter_line = format!(
-        "Filters — pod: {} | service: {} | external: {}",
+        "Granularity: {:?} | Filters — pod: {} | service: {} | external: {}",
+        app.granularity,
         app.filters.pod.as_deref().unwrap_or("(none)"),
         app.filters.service.as_deref().unwrap_or("(none)"),
         app.filters.external.as_deref().unwrap_or("(none)")
     );
 
-    let help = "[1] Graph  [2] Lists   [/] Pod filter   [s] Service filter   [e] External filter   [Ctrl+C] Clear filters   [q] Quit";
+    let help = "[1] Graph  [2] Lists  [3] Search path  [4] Errors  [5] Dangling  [6] AAAA   [/] Pod filter   [s] Service filter   [e] External filter   [Ctrl+C] Clear filters   [g] Granularity   [q] Quit";
 
     let p = Paragraph::new(vec![
         Line::from(Span::styled(filter_line, Style::default().fg(Color::White))),
@@ -745,4 +1021,5 @@ const PASTEL_3: Color = Color::Rgb(199, 206, 234); // lavender
 const PASTEL_4: Color = Color::Rgb(253, 255, 182); // butter
 const PASTEL_5: Color = Color::Rgb(255, 179, 186); // rose
 const PASTEL_6: Color = Color::Rgb(204, 255, 229); // aqua
+const PASTEL_7: Color = Color::Rgb(221, 198, 238); // lilac
 const PASTEL_EDGE: Color = Color::Rgb(200, 200, 200);